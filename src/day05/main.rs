@@ -1,4 +1,4 @@
-use std::fs;
+use std::{fmt, fs, process};
 use regex::Regex;
 
 type Stack = Vec<char>;
@@ -13,34 +13,105 @@ struct Move {
     size:usize,
     from:usize,
     to:usize,
+    line_no: usize,
+    source: String,
+}
+
+#[derive(Debug, PartialEq)]
+enum MoveError {
+    Malformed { line_no: usize, source: String },
+    UnknownStack { line_no: usize, source: String, stack: usize, stack_count: usize },
+    NotEnoughCrates { line_no: usize, source: String, stack: usize, requested: usize, available: usize },
+    // The input needs a drawing of the stacks, a blank line, then the moves
+    MissingSeparator,
+    MissingDrawing,
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MoveError::Malformed { line_no, source } =>
+                write!(f, "line {}: '{}' is not a valid move", line_no, source),
+            MoveError::UnknownStack { line_no, source, stack, stack_count } =>
+                write!(f, "line {}: '{}' refers to stack {} but there are only {} stacks",
+                    line_no, source, stack, stack_count),
+            MoveError::NotEnoughCrates { line_no, source, stack, requested, available } =>
+                write!(f, "line {}: '{}' moves {} crates but stack {} only holds {}",
+                    line_no, source, requested, stack, available),
+            MoveError::MissingSeparator =>
+                write!(f, "no blank line between the stack drawing and the moves"),
+            MoveError::MissingDrawing =>
+                write!(f, "no stack drawing before the moves"),
+        }
+    }
 }
 
 impl Move {
-    fn new(source: &str) -> Move {
-        let move_regex = Regex::new(r"move ([0-9]+) from ([0-9]+) to ([0-9]+)").unwrap();
+    fn new(source: &str, line_no: usize) -> Result<Move, MoveError> {
+        let move_regex = Regex::new(r"^move ([0-9]+) from ([0-9]+) to ([0-9]+)$").unwrap();
+        let malformed = || MoveError::Malformed { line_no, source: source.to_string() };
         let caps: Vec<usize> = move_regex
             .captures(source)
-            .unwrap()
+            .ok_or_else(malformed)?
             .iter()
             .skip(1) // Ignore the complete phrase match
-            .map(|x| x.unwrap().as_str().parse::<usize>().unwrap())
-            .collect();
-        Move{size: caps[0], from: caps[1] - 1, to: caps[2] - 1}
+            .map(|x| x.unwrap().as_str().parse::<usize>().map_err(|_| malformed()))
+            .collect::<Result<_, _>>()?;
+
+        // Stack numbers in the input start at 1
+        if caps[1] == 0 || caps[2] == 0 {
+            return Err(malformed());
+        }
+        Ok(Move{size: caps[0], from: caps[1] - 1, to: caps[2] - 1, line_no, source: source.to_string()})
     }
 
-    fn execute(&self, stacks: &mut Stacks, is_single_crate_moved: bool){
+    // Checks the move against the current state of the stacks without changing them
+    fn validate(&self, stacks: &Stacks) -> Result<(), MoveError> {
+        for stack_idx in [self.from, self.to] {
+            if stack_idx >= stacks.len() {
+                return Err(MoveError::UnknownStack {
+                    line_no: self.line_no,
+                    source: self.source.clone(),
+                    stack: stack_idx + 1,
+                    stack_count: stacks.len(),
+                });
+            }
+        }
+        if stacks[self.from].len() < self.size {
+            return Err(MoveError::NotEnoughCrates {
+                line_no: self.line_no,
+                source: self.source.clone(),
+                stack: self.from + 1,
+                requested: self.size,
+                available: stacks[self.from].len(),
+            });
+        }
+        Ok(())
+    }
+
+    fn execute(&self, stacks: &mut Stacks, is_single_crate_moved: bool) -> Result<(), MoveError> {
+        self.validate(stacks)?;
         let idx_to_split = stacks[self.from].len() - self.size;
         let crates_to_move = &mut stacks[self.from].split_off(idx_to_split);
         if is_single_crate_moved {
             crates_to_move.reverse();
         }
-        stacks[self.to].append(crates_to_move)
+        stacks[self.to].append(crates_to_move);
+        Ok(())
     }
 }
 
-fn get_code(stacks: Stacks) -> String {
+// Returns the top crate of each stack, or None where the stack is empty
+fn top_crates(stacks: &Stacks) -> Vec<Option<char>> {
     stacks.iter()
-        .map(|stack| stack.last().unwrap())
+        .map(|stack| stack.last().copied())
+        .collect()
+}
+
+// Builds the code from the top crates, an empty stack is shown as a space to match the drawing
+fn get_code(stacks: &Stacks) -> String {
+    top_crates(stacks).iter()
+        .map(|top| top.unwrap_or(' '))
         .collect::<String>()
 }
 
@@ -48,7 +119,7 @@ fn parse_crate_line(source: &str) -> Vec<CrateLocation> {
     source.chars()
         .collect::<Vec<char>>()
         .chunks(4) // Split into individual crates
-        .map(|item| item[1]) // Read the crate value from crate
+        .map(|item| *item.get(1).unwrap_or(&' ')) // Read the crate value from crate
         .enumerate() // Attach an index so the stack can be identified
         .filter(|(_, item_val)| *item_val != ' ') // Drop any positions that have no crates
         .map(|(i, val)| CrateLocation { stack_idx: i, crate_contents: val })
@@ -62,27 +133,163 @@ fn add_crate(stacks: &mut Stacks, crate_loc: CrateLocation){
     stacks[crate_loc.stack_idx].push(crate_loc.crate_contents)
 }
 
-fn main() {
-    let input_data = fs::read_to_string("src/day05/input.txt").unwrap();
-    let (stack_data, move_data) = input_data.split_once("\n\n").unwrap();
+// Draws the stacks in the same layout as the puzzle input, including the numbered base line
+fn draw_stacks(stacks: &Stacks) -> String {
+    let height = stacks.iter().map(|stack| stack.len()).max().unwrap_or(0);
+    let mut lines: Vec<String> = (0..height).rev()
+        .map(|level| stacks.iter()
+            .map(|stack| match stack.get(level) {
+                Some(c) => format!("[{}]", c),
+                None => "   ".to_string(),
+            })
+            .collect::<Vec<String>>()
+            .join(" "))
+        .collect();
+    lines.push((1..=stacks.len())
+        .map(|n| format!(" {} ", n))
+        .collect::<Vec<String>>()
+        .join(" "));
+    lines.join("\n")
+}
+
+fn parse_stacks(stack_data: &str) -> Result<Stacks, MoveError> {
+    // The numbered line also tells us how many stacks there are, even if some start empty
+    let stack_count = match stack_data.lines().last() {
+        Some(numbers) if !numbers.trim().is_empty() => numbers.split_whitespace().count(),
+        _ => return Err(MoveError::MissingDrawing),
+    };
 
-    let mut stacks_part1: Stacks = Stacks::new();
+    let mut stacks: Stacks = Stacks::new();
     stack_data.lines()
         .rev() // Start at the bottom of the stacks
         .skip(1) // Ignore the numbered line
-        .flat_map(|crate_line| parse_crate_line(crate_line))
-        .for_each(|c| add_crate(&mut stacks_part1, c));
-    let mut stacks_part2 = stacks_part1.clone();
+        .flat_map(parse_crate_line)
+        .for_each(|c| add_crate(&mut stacks, c));
 
-    let move_list:Vec<Move> = move_data.lines()
-        .map(|line| Move::new(line))
-        .collect();
+    stacks.resize(stacks.len().max(stack_count), Vec::new());
+    Ok(stacks)
+}
+
+fn parse_input(input_data: &str) -> Result<(Stacks, Vec<Move>), MoveError> {
+    let (stack_data, move_data) = input_data.split_once("\n\n").ok_or(MoveError::MissingSeparator)?;
+    let stacks = parse_stacks(stack_data)?;
+
+    // Moves start after the stack drawing and the blank separator line
+    let first_move_line = stack_data.lines().count() + 2;
+    let move_list = move_data.lines()
+        .enumerate()
+        .map(|(i, line)| Move::new(line, first_move_line + i))
+        .collect::<Result<Vec<Move>, MoveError>>()?;
+
+    Ok((stacks, move_list))
+}
+
+fn run_moves(mut stacks: Stacks, move_list: &[Move], is_single_crate_moved: bool) -> Result<Stacks, MoveError> {
+    for m in move_list {
+        m.execute(&mut stacks, is_single_crate_moved)?;
+    }
+    Ok(stacks)
+}
+
+fn main() {
+    let input_data = fs::read_to_string("src/day05/input.txt").unwrap();
+    let (stacks, move_list) = parse_input(&input_data).unwrap_or_else(|e| {
+        eprintln!("Invalid input - {}", e);
+        process::exit(1);
+    });
 
     // Part 1
-    move_list.iter().for_each(|m| {m.execute(&mut stacks_part1, true)});
-    println!("Part 1 - Code: {}", get_code(stacks_part1));
+    match run_moves(stacks.clone(), &move_list, true) {
+        Ok(stacks_part1) => {
+            println!("{}\n", draw_stacks(&stacks_part1));
+            println!("Part 1 - Code: {}", get_code(&stacks_part1));
+        }
+        Err(e) => eprintln!("Part 1 - Invalid move - {}", e),
+    }
 
     // Part 2
-    move_list.iter().for_each(|m| {m.execute(&mut stacks_part2, false)});
-    println!("Part 2 - Code: {}", get_code(stacks_part2));
-}
\ No newline at end of file
+    match run_moves(stacks, &move_list, false) {
+        Ok(stacks_part2) => {
+            println!("{}\n", draw_stacks(&stacks_part2));
+            println!("Part 2 - Code: {}", get_code(&stacks_part2));
+        }
+        Err(e) => eprintln!("Part 2 - Invalid move - {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn day05_example() {
+        let input_data = fs::read_to_string("src/day05/example.txt").unwrap();
+        let (stacks, move_list) = parse_input(&input_data).unwrap();
+        assert_eq!(get_code(&run_moves(stacks.clone(), &move_list, true).unwrap()), "CMZ");
+        assert_eq!(get_code(&run_moves(stacks, &move_list, false).unwrap()), "MCD");
+    }
+
+    #[test]
+    fn day05_draw_round_trip() {
+        let input_data = fs::read_to_string("src/day05/example.txt").unwrap();
+        let (stack_data, _) = input_data.split_once("\n\n").unwrap();
+        let stacks = parse_stacks(stack_data).unwrap();
+        assert_eq!(draw_stacks(&stacks), stack_data);
+        assert_eq!(parse_stacks(&draw_stacks(&stacks)), Ok(stacks));
+    }
+
+    #[test]
+    fn day05_draw_after_moves() {
+        let input_data = fs::read_to_string("src/day05/example.txt").unwrap();
+        let (stacks, move_list) = parse_input(&input_data).unwrap();
+        let stacks = run_moves(stacks, &move_list, true).unwrap();
+        let expected = [
+            "        [Z]",
+            "        [N]",
+            "        [D]",
+            "[C] [M] [P]",
+            " 1   2   3 ",
+        ].join("\n");
+        assert_eq!(draw_stacks(&stacks), expected);
+        assert_eq!(parse_stacks(&expected), Ok(stacks));
+    }
+
+    #[test]
+    fn day05_too_many_crates() {
+        let stacks = vec![vec!['A'], vec![]];
+        let m = Move::new("move 2 from 1 to 2", 7).unwrap();
+        assert_eq!(run_moves(stacks, &[m], true).err(), Some(MoveError::NotEnoughCrates {
+            line_no: 7,
+            source: "move 2 from 1 to 2".to_string(),
+            stack: 1,
+            requested: 2,
+            available: 1,
+        }));
+    }
+
+    #[test]
+    fn day05_unknown_stack() {
+        let stacks = vec![vec!['A'], vec![]];
+        let m = Move::new("move 1 from 1 to 3", 5).unwrap();
+        let err = run_moves(stacks, &[m], false).unwrap_err();
+        assert_eq!(err.to_string(), "line 5: 'move 1 from 1 to 3' refers to stack 3 but there are only 2 stacks");
+        assert!(Move::new("move 1 from 0 to 1", 1).is_err());
+        assert!(Move::new("move one from 1 to 2", 1).is_err());
+    }
+
+    #[test]
+    fn day05_empty_stack_code() {
+        let stacks = vec![vec!['A'], vec![], vec!['B', 'C']];
+        assert_eq!(top_crates(&stacks), vec![Some('A'), None, Some('C')]);
+        assert_eq!(get_code(&stacks), "A C");
+    }
+
+    #[test]
+    fn day05_malformed_input() {
+        assert_eq!(parse_input("[A]\n 1 \nmove 1 from 1 to 1").err(), Some(MoveError::MissingSeparator));
+        assert_eq!(parse_input("\n\nmove 1 from 1 to 1").err(), Some(MoveError::MissingDrawing));
+        assert_eq!(parse_input("   \n\nmove 1 from 1 to 1").err(), Some(MoveError::MissingDrawing));
+        let (stacks, _) = parse_input("[\n 1 \n\n").unwrap();
+        assert_eq!(stacks, vec![Vec::<char>::new()]);
+    }
+}
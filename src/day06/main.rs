@@ -1,25 +1,138 @@
-use std::{fs, collections::HashSet};
+use std::{collections::VecDeque, fs, io::{self, BufReader, Bytes, Read}};
 
-fn start_of_packet(source: &str, marker_size: usize) -> usize {
-    let input = source.chars().collect::<Vec<char>>();
+// Tracks the last `marker_size` symbols along with how often each one appears, so
+// checking the window for duplicates is constant time as each new symbol arrives
+struct MarkerDetector {
+    marker_size: usize,
+    window: VecDeque<u8>,
+    counts: [usize; 256],
+    duplicates: usize,
+    position: usize,
+}
+
+impl MarkerDetector {
+    fn new(marker_size: usize) -> MarkerDetector {
+        assert!(marker_size > 0, "Marker size must be at least 1");
+        MarkerDetector {
+            marker_size,
+            window: VecDeque::with_capacity(marker_size + 1),
+            counts: [0; 256],
+            duplicates: 0,
+            position: 0,
+        }
+    }
+
+    // Adds the next symbol, returning true if the window now ends on a marker
+    fn push(&mut self, symbol: u8) -> bool {
+        self.position += 1;
+        self.window.push_back(symbol);
+        self.counts[symbol as usize] += 1;
+        if self.counts[symbol as usize] > 1 {
+            self.duplicates += 1;
+        }
+
+        if self.window.len() > self.marker_size {
+            let dropped = self.window.pop_front().unwrap() as usize;
+            if self.counts[dropped] > 1 {
+                self.duplicates -= 1;
+            }
+            self.counts[dropped] -= 1;
+        }
+
+        self.window.len() == self.marker_size && self.duplicates == 0
+    }
+}
+
+// Iterates over the positions (number of symbols read) at which each marker completes
+struct Markers<R: Read> {
+    bytes: Bytes<BufReader<R>>,
+    detector: MarkerDetector,
+}
+
+impl<R: Read> Iterator for Markers<R> {
+    type Item = io::Result<usize>;
+
+    fn next(&mut self) -> Option<io::Result<usize>> {
+        for byte in self.bytes.by_ref() {
+            match byte {
+                Ok(symbol) => if self.detector.push(symbol) {
+                    return Some(Ok(self.detector.position));
+                },
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        None
+    }
+}
+
+fn markers<R: Read>(source: R, marker_size: usize) -> Markers<R> {
+    Markers {
+        bytes: BufReader::new(source).bytes(),
+        detector: MarkerDetector::new(marker_size),
+    }
+}
 
-    input.as_slice()
-        .windows(marker_size)
-        .map(|w| w.iter().map(|x|*x).collect::<HashSet<char>>())
-        .enumerate()
-        .find(|(_,set)| set.len() == marker_size)
-        .map(|(idx, _)| idx + marker_size)
-        .unwrap()
+// Finds the first marker in the stream, stopping as soon as it has been read
+fn start_of_packet<R: Read>(source: R, marker_size: usize) -> io::Result<Option<usize>> {
+    markers(source, marker_size).next().transpose()
+}
+
+fn all_markers<R: Read>(source: R, marker_size: usize) -> io::Result<Vec<usize>> {
+    markers(source, marker_size).collect()
 }
 
 fn main() {
     let input_data = fs::read_to_string("src/day06/input.txt").unwrap();
 
     input_data.lines().for_each(|line| {
-        println!("Part 1: Start index = {}", start_of_packet(line, 4))
+        match start_of_packet(line.as_bytes(), 4).unwrap() {
+            Some(idx) => println!("Part 1: Start index = {}", idx),
+            None => println!("Part 1: No start of packet marker found"),
+        }
+    });
+
+    input_data.lines().for_each(|line| {
+        match start_of_packet(line.as_bytes(), 14).unwrap() {
+            Some(idx) => println!("Part 2: Start index = {}", idx),
+            None => println!("Part 2: No start of message marker found"),
+        }
     });
 
     input_data.lines().for_each(|line| {
-        println!("Part 2: Start index = {:?}", start_of_packet(line, 14))
+        let message_markers = all_markers(line.as_bytes(), 14).unwrap();
+        println!("Message markers found = {}", message_markers.len())
     });
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn day06_example() {
+        let input_data = fs::read_to_string("src/day06/example.txt").unwrap();
+        let part1 = input_data.lines()
+            .map(|line| start_of_packet(line.as_bytes(), 4).unwrap())
+            .collect::<Vec<Option<usize>>>();
+        assert_eq!(part1, [Some(7), Some(5), Some(6), Some(10), Some(11)]);
+
+        let part2 = input_data.lines()
+            .map(|line| start_of_packet(line.as_bytes(), 14).unwrap())
+            .collect::<Vec<Option<usize>>>();
+        assert_eq!(part2, [Some(19), Some(23), Some(23), Some(29), Some(26)]);
+    }
+
+    #[test]
+    fn day06_no_marker() {
+        assert_eq!(start_of_packet("aabbaabb".as_bytes(), 3).unwrap(), None);
+        assert_eq!(start_of_packet("abc".as_bytes(), 4).unwrap(), None);
+        assert_eq!(all_markers("".as_bytes(), 1).unwrap(), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn day06_all_markers() {
+        assert_eq!(all_markers("abcabb".as_bytes(), 3).unwrap(), [3, 4, 5]);
+        assert_eq!(all_markers("aab".as_bytes(), 1).unwrap(), [1, 2, 3]);
+        assert_eq!(all_markers("aabcc".as_bytes(), 2).unwrap(), [3, 4]);
+    }
+}
@@ -1,4 +1,5 @@
-use std::{collections::HashMap, fs};
+use std::{collections::BTreeMap, env, fmt, fs};
+use serde_json::{json, Value};
 
 enum Cmd<'a> {
     CdRoot,
@@ -7,102 +8,868 @@ enum Cmd<'a> {
     Ls,
     Dir(&'a str),
     File(&'a str, u32),
+    // A line that is none of the above, kept so it can be reported
+    Invalid(&'a str),
 }
 
 impl Cmd<'_> {
-    fn parse<'a>(line: &'a str) -> Cmd<'a> {
-        if line.starts_with("$ cd") {
-            let dir = line.strip_prefix("$ cd ").unwrap();
+    fn parse(line: &str) -> Cmd<'_> {
+        if let Some(dir) = line.strip_prefix("$ cd ") {
             match dir {
-                "/" => return Cmd::CdRoot,
-                ".." => return Cmd::CdUp,
-                _ => return Cmd::Cd(dir),
+                "/" => Cmd::CdRoot,
+                ".." => Cmd::CdUp,
+                "" => Cmd::Invalid(line),
+                _ => Cmd::Cd(dir),
             }
         } else if line == "$ ls" {
-            return Cmd::Ls;
-        } else if line.starts_with("dir") {
-            return Cmd::Dir(line.strip_prefix("dir ").unwrap());
+            Cmd::Ls
+        } else if let Some(name) = line.strip_prefix("dir ").filter(|name| !name.is_empty()) {
+            Cmd::Dir(name)
         } else {
-            let (size_str, filename) = line.split_once(" ").unwrap();
-            return Cmd::File(filename, size_str.parse().unwrap());
+            match line.split_once(' ').map(|(size, name)| (size.parse(), name)) {
+                Some((Ok(size), name)) if !name.is_empty() => Cmd::File(name, size),
+                _ => Cmd::Invalid(line),
+            }
+        }
+    }
+}
+
+type NodeId = usize;
+const ROOT: NodeId = 0;
+
+enum NodeKind {
+    Dir { children: Vec<NodeId>, listed: bool },
+    File { size: u32 },
+}
+
+struct Node {
+    name: String,
+    parent: Option<NodeId>,
+    kind: NodeKind,
+}
+
+impl Node {
+    fn is_dir(&self) -> bool {
+        matches!(self.kind, NodeKind::Dir { .. })
+    }
+}
+
+// Directory tree stored as a flat list of nodes. A node is always added after its
+// parent, so every child has a larger id than the directory containing it
+struct FileSystem {
+    nodes: Vec<Node>,
+}
+
+impl FileSystem {
+    fn new() -> FileSystem {
+        FileSystem {
+            nodes: vec![Node {
+                name: String::from("/"),
+                parent: None,
+                kind: NodeKind::Dir { children: Vec::new(), listed: false },
+            }],
+        }
+    }
+
+    fn children(&self, dir: NodeId) -> &[NodeId] {
+        match &self.nodes[dir].kind {
+            NodeKind::Dir { children, .. } => children,
+            NodeKind::File { .. } => &[],
+        }
+    }
+
+    fn child(&self, dir: NodeId, name: &str) -> Option<NodeId> {
+        self.children(dir).iter().copied().find(|id| self.nodes[*id].name == name)
+    }
+
+    fn add(&mut self, dir: NodeId, name: &str, kind: NodeKind) -> NodeId {
+        let id = self.nodes.len();
+        self.nodes.push(Node { name: name.to_string(), parent: Some(dir), kind });
+        if let NodeKind::Dir { children, .. } = &mut self.nodes[dir].kind {
+            children.push(id);
+        }
+        id
+    }
+
+    // Marks the directory as listed, returning whether it had already been listed
+    fn mark_listed(&mut self, dir: NodeId) -> bool {
+        match &mut self.nodes[dir].kind {
+            NodeKind::Dir { listed, .. } => std::mem::replace(listed, true),
+            NodeKind::File { .. } => false,
+        }
+    }
+
+    fn path(&self, id: NodeId) -> String {
+        let mut names = Vec::new();
+        let mut current = id;
+        while let Some(parent) = self.nodes[current].parent {
+            names.push(self.nodes[current].name.as_str());
+            current = parent;
+        }
+        names.reverse();
+        format!("/{}", names.join("/"))
+    }
+
+    // Total size of every node, including everything below it for directories
+    fn sizes(&self) -> Vec<u32> {
+        let mut sizes: Vec<u32> = self.nodes.iter()
+            .map(|node| match node.kind {
+                NodeKind::File { size } => size,
+                NodeKind::Dir { .. } => 0,
+            })
+            .collect();
+        for id in (1..self.nodes.len()).rev() {
+            sizes[self.nodes[id].parent.unwrap()] += sizes[id];
+        }
+        sizes
+    }
+
+    // Lists every directory path with its total size, similar to `du`
+    fn du(&self) -> Vec<(String, u32)> {
+        let sizes = self.sizes();
+        (0..self.nodes.len())
+            .filter(|id| self.nodes[*id].is_dir())
+            .map(|id| (self.path(id), sizes[id]))
+            .collect()
+    }
+
+    // Finds all nodes matching the predicate, which is given the node and its total size
+    fn find<P: Fn(&Node, u32) -> bool>(&self, predicate: P) -> Vec<NodeId> {
+        let sizes = self.sizes();
+        (0..self.nodes.len())
+            .filter(|id| predicate(&self.nodes[*id], sizes[*id]))
+            .collect()
+    }
+
+    fn pre_order(&self) -> Vec<NodeId> {
+        let mut order = Vec::new();
+        let mut to_visit = vec![ROOT];
+        while let Some(id) = to_visit.pop() {
+            order.push(id);
+            to_visit.extend(self.children(id).iter().rev());
+        }
+        order
+    }
+
+    // For each node in a pre-order list, the position just past everything below it
+    fn subtree_ends(&self, order: &[NodeId]) -> Vec<usize> {
+        let mut ends = vec![order.len(); order.len()];
+        let mut open: Vec<usize> = Vec::new();
+        for (pos, id) in order.iter().enumerate() {
+            while let Some(top) = open.last() {
+                if self.is_ancestor(order[*top], *id) {
+                    break;
+                }
+                ends[*top] = pos;
+                open.pop();
+            }
+            open.push(pos);
+        }
+        ends
+    }
+
+    fn is_ancestor(&self, ancestor: NodeId, id: NodeId) -> bool {
+        let mut current = self.nodes[id].parent;
+        while let Some(parent) = current {
+            if parent == ancestor {
+                return true;
+            }
+            current = self.nodes[parent].parent;
+        }
+        false
+    }
+
+    fn draw_node(&self, f: &mut fmt::Formatter, id: NodeId, depth: usize) -> fmt::Result {
+        let node = &self.nodes[id];
+        match node.kind {
+            NodeKind::Dir { .. } => writeln!(f, "{}- {} (dir)", "  ".repeat(depth), node.name)?,
+            NodeKind::File { size } => writeln!(f, "{}- {} (file, size={})", "  ".repeat(depth), node.name, size)?,
+        }
+        for child in self.children(id) {
+            self.draw_node(f, *child, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+// Draws the tree in the same format as the puzzle description
+impl fmt::Display for FileSystem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.draw_node(f, ROOT, 0)
+    }
+}
+
+// Problems found in the transcript while building the tree, `line` starts at 1
+#[derive(Debug, PartialEq)]
+enum Issue {
+    RepeatedLs { line: usize, path: String },
+    DuplicateEntry { line: usize, path: String },
+    UnlistedCd { line: usize, path: String },
+    CdUpFromRoot { line: usize },
+    CdIntoFile { line: usize, path: String },
+    Malformed { line: usize, text: String },
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Issue::RepeatedLs { line, path } => write!(f, "line {}: {} has already been listed", line, path),
+            Issue::DuplicateEntry { line, path } => write!(f, "line {}: {} is listed more than once", line, path),
+            Issue::UnlistedCd { line, path } => write!(f, "line {}: cd into {} which was never listed", line, path),
+            Issue::CdUpFromRoot { line } => write!(f, "line {}: cd .. from the root directory", line),
+            Issue::CdIntoFile { line, path } => write!(f, "line {}: cd into {} which is a file", line, path),
+            Issue::Malformed { line, text } => write!(f, "line {}: '{}' is not a command or listing", line, text),
         }
     }
 }
 
 struct DirWalker<'a> {
     cmds: Vec<Cmd<'a>>,
-    cwd: Vec<&'a str>,
-    dir_sizes: HashMap<String, u32>,
+    cwd: NodeId,
+    fs: FileSystem,
+    issues: Vec<Issue>,
 }
 
 impl DirWalker<'_> {
-    fn new<'a>(cmds: Vec<Cmd<'a>>) -> DirWalker<'a> {
+    fn new(cmds: Vec<Cmd<'_>>) -> DirWalker<'_> {
         DirWalker {
-            cmds: cmds,
-            cwd: vec!["/"],
-            dir_sizes: HashMap::new(),
+            cmds,
+            cwd: ROOT,
+            fs: FileSystem::new(),
+            issues: Vec::new(),
         }
     }
 
     fn execute(&mut self) {
-        for cmd in self.cmds.iter() {
+        for (idx, cmd) in self.cmds.iter().enumerate() {
+            let line = idx + 1;
             match cmd {
-                Cmd::CdRoot => self.cwd = vec!["/"],
-                Cmd::CdUp => _ = self.cwd.pop(),
-                Cmd::Cd(dir) => self.cwd.push(dir),
-                Cmd::Dir(_) => (),
-                Cmd::Ls => (),
-                Cmd::File(_, size) => DirWalker::add_file(&self.cwd, &mut self.dir_sizes, *size),
+                Cmd::CdRoot => self.cwd = ROOT,
+                Cmd::CdUp => match self.fs.nodes[self.cwd].parent {
+                    Some(parent) => self.cwd = parent,
+                    None => self.issues.push(Issue::CdUpFromRoot { line }),
+                },
+                Cmd::Cd(dir) => match self.fs.child(self.cwd, dir) {
+                    Some(id) if self.fs.nodes[id].is_dir() => self.cwd = id,
+                    // Files can't hold entries, so stay where we are
+                    Some(id) => self.issues.push(Issue::CdIntoFile { line, path: self.fs.path(id) }),
+                    None => {
+                        // Still follow the cd so the rest of the transcript can be replayed
+                        let id = self.fs.add(self.cwd, dir, NodeKind::Dir { children: Vec::new(), listed: false });
+                        self.issues.push(Issue::UnlistedCd { line, path: self.fs.path(id) });
+                        self.cwd = id;
+                    }
+                },
+                Cmd::Ls => if self.fs.mark_listed(self.cwd) {
+                    self.issues.push(Issue::RepeatedLs { line, path: self.fs.path(self.cwd) });
+                },
+                Cmd::Dir(name) => DirWalker::add_entry(&mut self.fs, self.cwd, &mut self.issues, line, name,
+                    NodeKind::Dir { children: Vec::new(), listed: false }),
+                Cmd::File(name, size) => DirWalker::add_entry(&mut self.fs, self.cwd, &mut self.issues, line, name,
+                    NodeKind::File { size: *size }),
+                Cmd::Invalid(text) => self.issues.push(Issue::Malformed { line, text: text.to_string() }),
+            }
+        }
+    }
+
+    // Repeated entries are reported rather than added again so sizes are not counted twice
+    fn add_entry(fs: &mut FileSystem, cwd: NodeId, issues: &mut Vec<Issue>, line: usize, name: &str, kind: NodeKind) {
+        match fs.child(cwd, name) {
+            Some(id) => issues.push(Issue::DuplicateEntry { line, path: fs.path(id) }),
+            None => _ = fs.add(cwd, name, kind),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct JsonError {
+    path: String,
+    message: &'static str,
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+// Differences between two trees, by path
+#[derive(Debug, PartialEq)]
+enum Difference {
+    Removed(String),
+    Added(String),
+    KindChanged(String),
+    SizeChanged { path: String, before: u32, after: u32 },
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Difference::Removed(path) => write!(f, "- {}", path),
+            Difference::Added(path) => write!(f, "+ {}", path),
+            Difference::KindChanged(path) => write!(f, "! {} changed between file and dir", path),
+            Difference::SizeChanged { path, before, after } => write!(f, "~ {} {} -> {}", path, before, after),
+        }
+    }
+}
+
+impl FileSystem {
+    // Nested JSON objects, directories also carry their total size
+    fn to_json(&self) -> Value {
+        self.node_to_json(ROOT, &self.sizes())
+    }
+
+    fn node_to_json(&self, id: NodeId, sizes: &[u32]) -> Value {
+        let node = &self.nodes[id];
+        match node.kind {
+            NodeKind::Dir { .. } => json!({
+                "name": node.name,
+                "type": "dir",
+                "size": sizes[id],
+                "children": self.children(id).iter().map(|child| self.node_to_json(*child, sizes)).collect::<Vec<Value>>(),
+            }),
+            NodeKind::File { size } => json!({
+                "name": node.name,
+                "type": "file",
+                "size": size,
+            }),
+        }
+    }
+
+    // Rebuilds a tree from `to_json` output. Directory sizes are worked out from the
+    // files, so they are optional in the JSON
+    fn from_json(value: &Value) -> Result<FileSystem, JsonError> {
+        let mut fs = FileSystem::new();
+        match value.get("type").and_then(Value::as_str) {
+            Some("dir") => (),
+            _ => return Err(JsonError { path: String::from("/"), message: "root must be a dir" }),
+        }
+        fs.add_json_children(ROOT, value)?;
+        fs.mark_all_listed();
+        Ok(fs)
+    }
+
+    fn add_json_children(&mut self, dir: NodeId, value: &Value) -> Result<(), JsonError> {
+        let children = match value.get("children") {
+            None => return Ok(()),
+            Some(children) => children.as_array().ok_or_else(|| JsonError {
+                path: self.path(dir),
+                message: "children must be an array",
+            })?,
+        };
+        for child in children {
+            let name = child.get("name").and_then(Value::as_str)
+                .filter(|name| !name.is_empty() && !name.contains('/') && *name != "." && *name != "..")
+                .ok_or_else(|| JsonError { path: self.path(dir), message: "entry without a valid name" })?;
+            if self.child(dir, name).is_some() {
+                return Err(JsonError { path: self.join(dir, name), message: "duplicate entry" });
+            }
+            match child.get("type").and_then(Value::as_str) {
+                Some("dir") => {
+                    let id = self.add(dir, name, NodeKind::Dir { children: Vec::new(), listed: false });
+                    self.add_json_children(id, child)?;
+                }
+                Some("file") => {
+                    let size = child.get("size").and_then(Value::as_u64)
+                        .and_then(|size| u32::try_from(size).ok())
+                        .ok_or_else(|| JsonError { path: self.join(dir, name), message: "file needs a size" })?;
+                    self.add(dir, name, NodeKind::File { size });
+                }
+                _ => return Err(JsonError { path: self.join(dir, name), message: "type must be dir or file" }),
             }
         }
+        Ok(())
     }
 
-    fn add_file(cwd: &Vec<&str>, dir_sizes: &mut HashMap<String, u32>, file_size: u32) {
-        // Starting at the root directory, as the size to all containing folders
-        let mut path = String::from("");
-        cwd.iter().for_each(|x| {
-            if path != "/" && *x != "/" {
-                path.push_str("/");
+    fn join(&self, dir: NodeId, name: &str) -> String {
+        match dir {
+            ROOT => format!("/{}", name),
+            _ => format!("{}/{}", self.path(dir), name),
+        }
+    }
+
+    fn mark_all_listed(&mut self) {
+        for id in 0..self.nodes.len() {
+            self.mark_listed(id);
+        }
+    }
+
+    // Produces the shortest `cd`/`ls` session that lists every directory once, in a
+    // depth first walk. There is no `cd ..` after the last listing
+    fn transcript(&self) -> String {
+        let mut lines = vec![String::from("$ cd /")];
+        self.transcript_dir(ROOT, &mut lines);
+        while lines.last().map(String::as_str) == Some("$ cd ..") {
+            lines.pop();
+        }
+        lines.join("\n")
+    }
+
+    fn transcript_dir(&self, dir: NodeId, lines: &mut Vec<String>) {
+        lines.push(String::from("$ ls"));
+        for child in self.children(dir) {
+            let node = &self.nodes[*child];
+            match node.kind {
+                NodeKind::Dir { .. } => lines.push(format!("dir {}", node.name)),
+                NodeKind::File { size } => lines.push(format!("{} {}", size, node.name)),
             }
-            path.push_str(x);
-            dir_sizes
-                .entry(path.to_owned())
-                .and_modify(|y| *y += file_size)
-                .or_insert(file_size);
-        })
+        }
+        for child in self.children(dir).iter().filter(|id| self.nodes[**id].is_dir()) {
+            lines.push(format!("$ cd {}", self.nodes[*child].name));
+            self.transcript_dir(*child, lines);
+            lines.push(String::from("$ cd .."));
+        }
+    }
+
+    // Compares by path, so listing order and the order of commands do not matter
+    fn diff(&self, other: &FileSystem) -> Vec<Difference> {
+        let entries = |fs: &FileSystem| -> BTreeMap<String, (bool, u32)> {
+            let sizes = fs.sizes();
+            (1..fs.nodes.len())
+                .map(|id| (fs.path(id), (fs.nodes[id].is_dir(), sizes[id])))
+                .collect()
+        };
+        let before = entries(self);
+        let after = entries(other);
+
+        let mut differences = Vec::new();
+        for (path, (was_dir, old_size)) in before.iter() {
+            match after.get(path) {
+                None => differences.push(Difference::Removed(path.clone())),
+                Some((is_dir, _)) if is_dir != was_dir => differences.push(Difference::KindChanged(path.clone())),
+                Some((false, new_size)) if new_size != old_size => differences.push(Difference::SizeChanged {
+                    path: path.clone(),
+                    before: *old_size,
+                    after: *new_size,
+                }),
+                _ => (),
+            }
+        }
+        for path in after.keys().filter(|path| !before.contains_key(*path)) {
+            differences.push(Difference::Added(path.clone()));
+        }
+        differences
+    }
+}
+
+struct Device {
+    disk_size: u32,
+    required_space: u32,
+}
+
+impl Device {
+    fn free_space(&self, used_size: u32) -> u32 {
+        self.disk_size.saturating_sub(used_size)
+    }
+
+    // How much has to be deleted, zero if there is already enough free space
+    fn need_to_free(&self, used_size: u32) -> u32 {
+        self.required_space.saturating_sub(self.free_space(used_size))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum PlanError {
+    DiskTooSmall { disk_size: u32, required_space: u32 },
+    LimitTooLarge { limit: u32, max_limit: u32 },
+}
+
+impl fmt::Display for PlanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PlanError::DiskTooSmall { disk_size, required_space } =>
+                write!(f, "{} required but the disk only holds {}", required_space, disk_size),
+            PlanError::LimitTooLarge { limit, max_limit } =>
+                write!(f, "planning up to {} is past the limit of {}", limit, max_limit),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct Plan {
+    paths: Vec<String>,
+    freed: u32,
+}
+
+// Fixed size set of sums, used to track which totals can be freed
+#[derive(Clone)]
+struct BitSet {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl BitSet {
+    fn new(len: usize) -> BitSet {
+        BitSet { words: vec![0; len.div_ceil(64)], len }
+    }
+
+    fn contains(&self, idx: usize) -> bool {
+        idx < self.len && self.words[idx / 64] & (1 << (idx % 64)) != 0
+    }
+
+    fn insert(&mut self, idx: usize) {
+        self.words[idx / 64] |= 1 << (idx % 64);
+    }
+
+    // Adds every value of `other` increased by `shift`, dropping anything past the end
+    fn union_shifted(&mut self, other: &BitSet, shift: usize) {
+        let word_shift = shift / 64;
+        let bit_shift = shift % 64;
+        for idx in word_shift..self.words.len() {
+            let src = idx - word_shift;
+            let mut word = other.words[src] << bit_shift;
+            if bit_shift > 0 && src > 0 {
+                word |= other.words[src - 1] >> (64 - bit_shift);
+            }
+            self.words[idx] |= word;
+        }
+        if !self.len.is_multiple_of(64) {
+            *self.words.last_mut().unwrap() &= (1 << (self.len % 64)) - 1;
+        }
+    }
+
+    // Adds `other`, calling `on_new` for each value that was not already present
+    fn union_with<F: FnMut(usize)>(&mut self, other: &BitSet, mut on_new: F) {
+        for (idx, (word, other_word)) in self.words.iter_mut().zip(other.words.iter()).enumerate() {
+            let mut added = other_word & !*word;
+            *word |= other_word;
+            while added != 0 {
+                on_new(idx * 64 + added.trailing_zeros() as usize);
+                added &= added - 1;
+            }
+        }
     }
 }
 
+// Largest sum the planner tracks, keeping its tables to a few hundred megabytes
+const MAX_PLAN_LIMIT: u32 = 1 << 25;
+
+// Finds the smallest total of directories (and optionally files) to delete, with no chosen
+// item inside another, that frees up enough space on the device.
+//
+// Candidates are laid out in pre-order, so from each position we can either delete the
+// candidate and jump past everything below it, or keep it and move on to its first child.
+// Every route through the positions is a valid non-nested selection, and the sums reachable
+// at a position only grow as we move forward, so recording where each sum first appears is
+// enough to walk back and recover the chosen paths.
+fn plan_deletion(fs: &FileSystem, device: &Device, include_files: bool) -> Result<Plan, PlanError> {
+    if device.required_space > device.disk_size {
+        return Err(PlanError::DiskTooSmall { disk_size: device.disk_size, required_space: device.required_space });
+    }
+    let sizes = fs.sizes();
+    let need_to_free = device.need_to_free(sizes[ROOT]);
+    if need_to_free == 0 {
+        return Ok(Plan { paths: Vec::new(), freed: 0 });
+    }
+
+    let order = fs.pre_order()
+        .into_iter()
+        .filter(|id| include_files || fs.nodes[*id].is_dir())
+        .collect::<Vec<NodeId>>();
+    let ends = fs.subtree_ends(&order);
+
+    // Deleting the smallest single candidate that is big enough is always possible, so no
+    // better plan can free more than that
+    let limit = order.iter()
+        .map(|id| sizes[*id])
+        .filter(|size| *size >= need_to_free)
+        .min()
+        .unwrap();
+    if limit > MAX_PLAN_LIMIT {
+        return Err(PlanError::LimitTooLarge { limit, max_limit: MAX_PLAN_LIMIT });
+    }
+    let limit = limit as usize;
+
+    let mut first_seen = vec![u32::MAX; limit + 1];
+    first_seen[0] = 0;
+    let mut reachable = BitSet::new(limit + 1);
+    reachable.insert(0);
+    let mut pending: Vec<Option<BitSet>> = vec![None; order.len() + 1];
+    for (pos, id) in order.iter().enumerate() {
+        let size = sizes[*id] as usize;
+        if size <= limit {
+            pending[ends[pos]]
+                .get_or_insert_with(|| BitSet::new(limit + 1))
+                .union_shifted(&reachable, size);
+        }
+        if let Some(arrived) = pending[pos + 1].take() {
+            reachable.union_with(&arrived, |sum| first_seen[sum] = pos as u32 + 1);
+        }
+    }
+
+    let freed = (need_to_free as usize..=limit).find(|sum| reachable.contains(*sum)).unwrap();
+
+    let mut paths = Vec::new();
+    let mut remaining = freed;
+    while remaining > 0 {
+        let arrived_at = first_seen[remaining] as usize;
+        let pos = (0..arrived_at)
+            .find(|pos| {
+                let size = sizes[order[*pos]] as usize;
+                ends[*pos] == arrived_at && size > 0 && size <= remaining && first_seen[remaining - size] as usize <= *pos
+            })
+            .unwrap();
+        paths.push(fs.path(order[pos]));
+        remaining -= sizes[order[pos]] as usize;
+    }
+    paths.sort();
+
+    Ok(Plan { paths, freed: freed as u32 })
+}
+
+fn read_transcript(path: &str) -> FileSystem {
+    let input_data = fs::read_to_string(path).unwrap();
+    let mut dir_walker = DirWalker::new(input_data.lines().map(Cmd::parse).collect());
+    dir_walker.execute();
+    dir_walker.issues.iter().for_each(|issue| eprintln!("Warning - {}: {}", path, issue));
+    dir_walker.fs
+}
+
+fn read_json(path: &str) -> FileSystem {
+    let value: Value = serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+    FileSystem::from_json(&value).unwrap_or_else(|e| panic!("Invalid tree in {} - {}", path, e))
+}
+
+// With no arguments this solves the puzzle, otherwise it converts between transcripts and JSON:
+//   json <transcript>          prints the tree as JSON
+//   transcript <json>          prints a transcript that rebuilds the tree
+//   diff <transcript> <transcript>
 fn main() {
-    let input_data = fs::read_to_string("src/day07/input.txt").unwrap();
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    match args.iter().map(String::as_str).collect::<Vec<&str>>().as_slice() {
+        ["json", path] => println!("{}", serde_json::to_string_pretty(&read_transcript(path).to_json()).unwrap()),
+        ["transcript", path] => println!("{}", read_json(path).transcript()),
+        ["diff", before, after] => read_transcript(before)
+            .diff(&read_transcript(after))
+            .iter()
+            .for_each(|difference| println!("{}", difference)),
+        [] => solve("src/day07/input.txt"),
+        _ => eprintln!("Usage: day07 [json <transcript> | transcript <json> | diff <transcript> <transcript>]"),
+    }
+}
+
+fn solve(path: &str) {
+    let input_data = fs::read_to_string(path).unwrap();
     let input = input_data
         .lines()
-        .map(|line| Cmd::parse(line))
+        .map(Cmd::parse)
         .collect::<Vec<Cmd>>();
 
     let mut dir_walker = DirWalker::new(input);
     dir_walker.execute();
+    dir_walker.issues.iter().for_each(|issue| println!("Warning - {}", issue));
 
+    let sizes = dir_walker.fs.sizes();
     let total: u32 = dir_walker
-        .dir_sizes
-        .values()
-        .filter(|size| **size <= 100000)
+        .fs
+        .find(|node, size| node.is_dir() && size <= 100000)
+        .iter()
+        .map(|id| sizes[*id])
         .sum();
 
     println!("Part 1 : Total {}", total);
 
-    const DISK_SIZE: u32 = 70000000;
-    const REQ_SIZE: u32 = 30000000;
-    let used_size = dir_walker.dir_sizes.get("/").unwrap();
-    let free_size = DISK_SIZE - used_size;
-    let need_to_free = REQ_SIZE - free_size;
+    let device = Device { disk_size: 70000000, required_space: 30000000 };
+    let need_to_free = device.need_to_free(sizes[ROOT]);
+    if need_to_free == 0 {
+        println!("Part 2 : Already {} free", device.free_space(sizes[ROOT]));
+        return;
+    }
 
     let min_size = dir_walker
-        .dir_sizes
-        .values()
-        .filter(|size| **size > need_to_free)
+        .fs
+        .du()
+        .iter()
+        .map(|(_, size)| *size)
+        .filter(|size| *size > need_to_free)
         .min()
         .unwrap();
 
     println!("Part 2 : Min Size {}", min_size);
+
+    for include_files in [false, true] {
+        match plan_deletion(&dir_walker.fs, &device, include_files) {
+            Ok(plan) => println!("Plan (files included: {}) : Frees {} by deleting {}",
+                include_files, plan.freed, plan.paths.join(", ")),
+            Err(e) => println!("Plan (files included: {}) : {}", include_files, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn walk(transcript: &str) -> DirWalker<'_> {
+        let mut dir_walker = DirWalker::new(transcript.lines().map(Cmd::parse).collect());
+        dir_walker.execute();
+        dir_walker
+    }
+
+    #[test]
+    fn day07_example_tree() {
+        let input_data = fs::read_to_string("src/day07/example.txt").unwrap();
+        let dir_walker = walk(&input_data);
+        let expected = [
+            "- / (dir)",
+            "  - a (dir)",
+            "    - e (dir)",
+            "      - i (file, size=584)",
+            "    - f (file, size=29116)",
+            "    - g (file, size=2557)",
+            "    - h.lst (file, size=62596)",
+            "  - b.txt (file, size=14848514)",
+            "  - c.dat (file, size=8504156)",
+            "  - d (dir)",
+            "    - j (file, size=4060174)",
+            "    - d.log (file, size=8033020)",
+            "    - d.ext (file, size=5626152)",
+            "    - k (file, size=7214296)",
+        ];
+        assert_eq!(dir_walker.fs.to_string().lines().collect::<Vec<&str>>(), expected);
+        assert!(dir_walker.issues.is_empty());
+    }
+
+    #[test]
+    fn day07_example_du() {
+        let input_data = fs::read_to_string("src/day07/example.txt").unwrap();
+        let dir_walker = walk(&input_data);
+        let mut du = dir_walker.fs.du();
+        du.sort();
+        assert_eq!(du, [
+            ("/".to_string(), 48381165),
+            ("/a".to_string(), 94853),
+            ("/a/e".to_string(), 584),
+            ("/d".to_string(), 24933642),
+        ]);
+    }
+
+    #[test]
+    fn day07_find() {
+        let input_data = fs::read_to_string("src/day07/example.txt").unwrap();
+        let fs = walk(&input_data).fs;
+        let logs = fs.find(|node, _| !node.is_dir() && node.name.starts_with("d."));
+        assert_eq!(logs.iter().map(|id| fs.path(*id)).collect::<Vec<String>>(), ["/d/d.log", "/d/d.ext"]);
+        let small_dirs = fs.find(|node, size| node.is_dir() && size <= 100000);
+        assert_eq!(small_dirs.iter().map(|id| fs.path(*id)).collect::<Vec<String>>(), ["/a", "/a/e"]);
+    }
+
+    #[test]
+    fn day07_plan_deletion() {
+        let input_data = fs::read_to_string("src/day07/example.txt").unwrap();
+        let fs = walk(&input_data).fs;
+        let device = Device { disk_size: 70000000, required_space: 30000000 };
+        assert_eq!(plan_deletion(&fs, &device, false), Ok(Plan { paths: vec!["/d".to_string()], freed: 24933642 }));
+        assert_eq!(plan_deletion(&fs, &device, true), Ok(Plan { paths: vec!["/c.dat".to_string()], freed: 8504156 }));
+
+        // Combining items only beats the single best item when files can be picked
+        let device = Device { disk_size: 48381165, required_space: 8600000 };
+        assert_eq!(plan_deletion(&fs, &device, false), Ok(Plan { paths: vec!["/d".to_string()], freed: 24933642 }));
+        assert_eq!(plan_deletion(&fs, &device, true), Ok(Plan {
+            paths: vec!["/d/d.ext".to_string(), "/d/j".to_string()],
+            freed: 9686326,
+        }));
+
+        let device = Device { disk_size: 48411165, required_space: 60000 };
+        assert_eq!(plan_deletion(&fs, &device, false), Ok(Plan { paths: vec!["/a".to_string()], freed: 94853 }));
+        assert_eq!(plan_deletion(&fs, &device, true), Ok(Plan {
+            paths: vec!["/a/f".to_string(), "/a/g".to_string()],
+            freed: 31673,
+        }));
+    }
+
+    #[test]
+    fn day07_plan_enough_space() {
+        let input_data = fs::read_to_string("src/day07/example.txt").unwrap();
+        let fs = walk(&input_data).fs;
+        let device = Device { disk_size: 100000000, required_space: 30000000 };
+        assert_eq!(device.need_to_free(48381165), 0);
+        assert_eq!(plan_deletion(&fs, &device, false), Ok(Plan { paths: Vec::new(), freed: 0 }));
+
+        let device = Device { disk_size: 100, required_space: 200 };
+        assert_eq!(device.need_to_free(48381165), 200);
+        assert_eq!(plan_deletion(&fs, &device, false),
+            Err(PlanError::DiskTooSmall { disk_size: 100, required_space: 200 }));
+    }
+
+    #[test]
+    fn day07_plan_large_disk() {
+        let input_data = fs::read_to_string("src/day07/example.txt").unwrap();
+        let fs = walk(&input_data).fs;
+        let device = Device { disk_size: 4000000000, required_space: 3990000000 };
+        assert_eq!(device.need_to_free(48381165), 38381165);
+        assert_eq!(plan_deletion(&fs, &device, false),
+            Err(PlanError::LimitTooLarge { limit: 48381165, max_limit: MAX_PLAN_LIMIT }));
+
+        let device = Device { disk_size: 4000000000, required_space: 3960000000 };
+        assert_eq!(plan_deletion(&fs, &device, false), Ok(Plan { paths: vec!["/d".to_string()], freed: 24933642 }));
+    }
+
+    #[test]
+    fn day07_json_round_trip() {
+        let input_data = fs::read_to_string("src/day07/example.txt").unwrap();
+        let fs = walk(&input_data).fs;
+        let value = fs.to_json();
+        assert_eq!(value["size"], 48381165);
+        assert_eq!(value["children"][0]["name"], "a");
+        assert_eq!(value["children"][0]["children"][0]["children"][0], json!({"name": "i", "type": "file", "size": 584}));
+
+        let rebuilt = FileSystem::from_json(&value).unwrap();
+        assert_eq!(rebuilt.to_string(), fs.to_string());
+        assert_eq!(rebuilt.transcript(), input_data.trim_end());
+
+        // The generated transcript replays cleanly through the walker
+        let transcript = fs.transcript();
+        let replayed = walk(&transcript);
+        assert!(replayed.issues.is_empty());
+        assert!(replayed.fs.diff(&fs).is_empty());
+    }
+
+    #[test]
+    fn day07_json_errors() {
+        let err = FileSystem::from_json(&json!({"type": "dir", "children": [
+            {"name": "a", "type": "dir", "children": [{"name": "b", "type": "file"}]},
+        ]}));
+        assert_eq!(err.err(), Some(JsonError { path: "/a/b".to_string(), message: "file needs a size" }));
+        let err = FileSystem::from_json(&json!({"type": "dir", "children": [{"name": "..", "type": "dir"}]}));
+        assert_eq!(err.err(), Some(JsonError { path: "/".to_string(), message: "entry without a valid name" }));
+        assert!(FileSystem::from_json(&json!({"type": "file", "size": 1})).is_err());
+        assert!(FileSystem::from_json(&json!({"type": "dir", "children": [{"name": "x", "type": "link"}]})).is_err());
+    }
+
+    #[test]
+    fn day07_diff() {
+        let before = walk("$ cd /\n$ ls\ndir a\n10 b\n$ cd a\n$ ls\n5 c\n3 d").fs;
+        let after = walk("$ cd /\n$ ls\n12 b\ndir a\n$ cd a\n$ ls\n5 c\ndir d\n1 e").fs;
+        assert_eq!(before.diff(&after), [
+            Difference::KindChanged("/a/d".to_string()),
+            Difference::SizeChanged { path: "/b".to_string(), before: 10, after: 12 },
+            Difference::Added("/a/e".to_string()),
+        ]);
+        assert_eq!(after.diff(&before)[1].to_string(), "- /a/e");
+    }
+
+    #[test]
+    fn day07_transcript_issues() {
+        let dir_walker = walk("$ cd /\n$ ls\n100 a\n100 a\n$ ls\n$ cd b\n$ ls\n5 c\n$ cd ..\n$ cd ..");
+        assert_eq!(dir_walker.issues, [
+            Issue::DuplicateEntry { line: 4, path: "/a".to_string() },
+            Issue::RepeatedLs { line: 5, path: "/".to_string() },
+            Issue::UnlistedCd { line: 6, path: "/b".to_string() },
+            Issue::CdUpFromRoot { line: 10 },
+        ]);
+        assert_eq!(dir_walker.fs.sizes()[ROOT], 105);
+
+        let dir_walker = walk("$ cd /\n$ ls\n7 a.txt\n$ cd a.txt\n$ ls\n5 b\n$ cd\nls\nx c\n12");
+        assert_eq!(dir_walker.issues, [
+            Issue::CdIntoFile { line: 4, path: "/a.txt".to_string() },
+            Issue::RepeatedLs { line: 5, path: "/".to_string() },
+            Issue::Malformed { line: 7, text: "$ cd".to_string() },
+            Issue::Malformed { line: 8, text: "ls".to_string() },
+            Issue::Malformed { line: 9, text: "x c".to_string() },
+            Issue::Malformed { line: 10, text: "12".to_string() },
+        ]);
+        let sizes = dir_walker.fs.sizes();
+        assert_eq!((sizes[ROOT], sizes[dir_walker.fs.child(ROOT, "a.txt").unwrap()]), (12, 7));
+    }
 }
@@ -1,127 +1,275 @@
-use std::fs;
-use std::collections::HashSet;
+use std::{env, fs};
 
-fn main() {
-    let input_data = fs::read_to_string("src/day08/input.txt").unwrap();
+// How many trees can be seen from a tree in each direction before the view is blocked
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Sightlines {
+    up: usize,
+    down: usize,
+    left: usize,
+    right: usize,
+}
 
-    let grid = input_data.lines()
-        .map(|line| line.chars()
-            .map(|x| x.to_digit(10).unwrap())
-            .collect::<Vec<u32>>())
-        .collect::<Vec<Vec<u32>>>();
-
-    let mut seen_trees: HashSet<(usize, usize)> = HashSet::new();
-    let grid_size = grid[0].len();
-
-    
-    for j in 0..grid_size {
-        let mut last: i32 = -1;
-        for i in 0..grid_size {
-            let tree = grid[j][i] as i32;
-            if tree > last {
-                last = tree;
-                seen_trees.insert((i,j));
-                if last == 9 {
-                    break
-                }
-            }
+impl Sightlines {
+    fn scenic_score(&self) -> usize {
+        self.up * self.down * self.left * self.right
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct TreeView {
+    visible: bool,
+    sightlines: Sightlines,
+}
+
+struct Forest {
+    width: usize,
+    height: usize,
+    trees: Vec<u8>,
+}
+
+impl Forest {
+    fn new(source: &str) -> Forest {
+        let rows = source.lines()
+            .map(|line| line.chars()
+                .map(|x| x.to_digit(10).unwrap() as u8)
+                .collect::<Vec<u8>>())
+            .collect::<Vec<Vec<u8>>>();
+        let width = rows.first().map_or(0, |row| row.len());
+        if let Some(j) = rows.iter().position(|row| row.len() != width) {
+            panic!("Row {} has {} trees, expected {}", j, rows[j].len(), width);
         }
+        Forest { width, height: rows.len(), trees: rows.concat() }
+    }
+
+    fn idx(&self, i: usize, j: usize) -> usize {
+        j * self.width + i
     }
 
-    for j in 0..grid_size {
-        let mut last: i32 = -1;
-        for i in (0..grid_size).rev() {
-            let tree = grid[j][i] as i32;
-            if tree > last {
-                last = tree;
-                seen_trees.insert((i,j));
-                if last == 9 {
-                    break
+    fn coords(&self, idx: usize) -> (usize, usize) {
+        (idx % self.width, idx / self.width)
+    }
+
+    // Walks a line of trees starting from the edge, returning for each tree whether it can be
+    // seen from that edge and how far it can see back towards it. A stack holds the trees
+    // that are still taller than everything after them, so each tree is pushed and popped once
+    fn scan_line(&self, line: &[usize]) -> Vec<(bool, usize)> {
+        let mut taller: Vec<usize> = Vec::new();
+        line.iter()
+            .enumerate()
+            .map(|(pos, idx)| {
+                let tree = self.trees[*idx];
+                while taller.last().is_some_and(|prev| self.trees[line[*prev]] < tree) {
+                    taller.pop();
                 }
+                let result = match taller.last() {
+                    Some(blocker) => (false, pos - blocker),
+                    None => (true, pos),
+                };
+                taller.push(pos);
+                result
+            })
+            .collect()
+    }
+
+    // Visibility and viewing distances for every tree, in row order
+    fn survey(&self) -> Vec<TreeView> {
+        let mut views = vec![TreeView::default(); self.trees.len()];
+        let rows = (0..self.height)
+            .map(|j| (0..self.width).map(|i| self.idx(i, j)).collect::<Vec<usize>>())
+            .collect::<Vec<Vec<usize>>>();
+        let cols = (0..self.width)
+            .map(|i| (0..self.height).map(|j| self.idx(i, j)).collect::<Vec<usize>>())
+            .collect::<Vec<Vec<usize>>>();
+
+        let mut apply = |line: &[usize], set: fn(&mut Sightlines, usize)| {
+            for (idx, (visible, distance)) in line.iter().zip(self.scan_line(line)) {
+                views[*idx].visible |= visible;
+                set(&mut views[*idx].sightlines, distance);
             }
+        };
+        for row in rows.iter() {
+            apply(row, |s, d| s.left = d);
+            let reversed = row.iter().rev().copied().collect::<Vec<usize>>();
+            apply(&reversed, |s, d| s.right = d);
         }
+        for col in cols.iter() {
+            apply(col, |s, d| s.up = d);
+            let reversed = col.iter().rev().copied().collect::<Vec<usize>>();
+            apply(&reversed, |s, d| s.down = d);
+        }
+        views
     }
 
-    for i in 0..grid_size {
-        let mut last: i32 = -1;
-        for j in 0..grid_size {
-            let tree = grid[j][i] as i32;
-            if tree > last {
-                last = tree;
-                seen_trees.insert((i,j));
-                if last == 9 {
-                    break
-                }
+    // The coordinates and view of the tree with the highest scenic score
+    fn best_tree(&self, views: &[TreeView]) -> Option<((usize, usize), TreeView)> {
+        views.iter()
+            .enumerate()
+            .max_by_key(|(_, view)| view.sightlines.scenic_score())
+            .map(|(idx, view)| (self.coords(idx), *view))
+    }
+}
+
+// Characters used for the ASCII heatmap, from lowest to highest score
+const HEAT_RAMP: &[u8] = b" .:-=+*#%@";
+
+impl Forest {
+    fn render<F: Fn(usize) -> char>(&self, cell: F) -> String {
+        (0..self.height)
+            .map(|j| (0..self.width).map(|i| cell(self.idx(i, j))).collect::<String>())
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    // Visible trees as `#`, hidden ones as `.`
+    fn visibility_map(&self, views: &[TreeView]) -> String {
+        self.render(|idx| if views[idx].visible { '#' } else { '.' })
+    }
+
+    // Scenic scores on a log scale, as a few trees score far higher than the rest. The best
+    // tree is always `@` and trees on the edge (score 0) are blank
+    fn scenic_heatmap(&self, views: &[TreeView]) -> String {
+        let scores = scenic_scores(views);
+        let max = scores.iter().copied().max().unwrap_or(0).max(1);
+        let top = (HEAT_RAMP.len() - 1) as f64;
+        self.render(|idx| {
+            let level = ((scores[idx] as f64).ln_1p() / (max as f64).ln_1p() * top) as usize;
+            HEAT_RAMP[level] as char
+        })
+    }
+
+    fn to_csv(&self, values: &[usize]) -> String {
+        (0..self.height)
+            .map(|j| (0..self.width)
+                .map(|i| values[self.idx(i, j)].to_string())
+                .collect::<Vec<String>>()
+                .join(","))
+            .map(|row| row + "\n")
+            .collect()
+    }
+
+    // Binary greyscale image with the values scaled so the largest is white
+    fn to_pgm(&self, values: &[usize]) -> Vec<u8> {
+        let max = values.iter().copied().max().unwrap_or(0).max(1);
+        let mut image = format!("P5\n{} {}\n255\n", self.width, self.height).into_bytes();
+        image.extend(values.iter().map(|value| (value * 255 / max) as u8));
+        image
+    }
+
+    // Binary colour image, visible trees are green and hidden ones grey, brighter when taller
+    fn to_ppm(&self, views: &[TreeView]) -> Vec<u8> {
+        let mut image = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        for (tree, view) in self.trees.iter().zip(views) {
+            let shade = 60 + *tree * 20;
+            match view.visible {
+                true => image.extend([0, shade, 0]),
+                false => image.extend([shade / 2, shade / 2, shade / 2]),
             }
         }
+        image
     }
+}
 
-    for i in 0..grid_size {
-        let mut last: i32 = -1;
-        for j in (0..grid_size).rev() {
-            let tree = grid[j][i] as i32;
-            if tree > last {
-                last = tree;
-                seen_trees.insert((i,j));
-                if last == 9 {
-                    break
-                }
-            }
+fn scenic_scores(views: &[TreeView]) -> Vec<usize> {
+    views.iter().map(|view| view.sightlines.scenic_score()).collect()
+}
+
+// 1 for visible trees and 0 for hidden ones
+fn visibility_mask(views: &[TreeView]) -> Vec<usize> {
+    views.iter().map(|view| view.visible as usize).collect()
+}
+
+fn best_tree_report(forest: &Forest, views: &[TreeView]) -> String {
+    match forest.best_tree(views) {
+        Some(((i, j), view)) => format!(
+            "Best tree at ({}, {}) with height {} - up {}, down {}, left {}, right {}, score {}",
+            i, j, forest.trees[forest.idx(i, j)],
+            view.sightlines.up, view.sightlines.down, view.sightlines.left, view.sightlines.right,
+            view.sightlines.scenic_score()),
+        None => String::from("No trees"),
+    }
+}
+
+// With no arguments this solves the puzzle, otherwise it dumps the survey of the input:
+//   visible | heatmap          ASCII maps
+//   csv [visible]              scenic scores or visibility mask as a CSV matrix
+//   pgm <file> | ppm <file>    scenic score or visibility image
+fn main() {
+    let input_data = fs::read_to_string("src/day08/input.txt").unwrap();
+    let forest = Forest::new(&input_data);
+    let views = forest.survey();
+
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    match args.iter().map(String::as_str).collect::<Vec<&str>>().as_slice() {
+        ["visible"] => println!("{}", forest.visibility_map(&views)),
+        ["heatmap"] => println!("{}", forest.scenic_heatmap(&views)),
+        ["csv"] => print!("{}", forest.to_csv(&scenic_scores(&views))),
+        ["csv", "visible"] => print!("{}", forest.to_csv(&visibility_mask(&views))),
+        ["pgm", path] => fs::write(path, forest.to_pgm(&scenic_scores(&views))).unwrap(),
+        ["ppm", path] => fs::write(path, forest.to_ppm(&views)).unwrap(),
+        [] => {
+            println!("Part 1 - Visible trees: {}", views.iter().filter(|view| view.visible).count());
+            println!("Part 2 - {}", best_tree_report(&forest, &views));
         }
+        _ => eprintln!("Usage: day08 [visible | heatmap | csv [visible] | pgm <file> | ppm <file>]"),
     }
+}
 
-    println!("{:?}", seen_trees.len());
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let mut max = 0;
-    for j in 0..grid_size {
-        for i in 0..grid_size {
-            let tree = grid[j][i];
+    #[test]
+    fn day08_example() {
+        let input_data = fs::read_to_string("src/day08/example.txt").unwrap();
+        let forest = Forest::new(&input_data);
+        let views = forest.survey();
+        assert_eq!(views.iter().filter(|view| view.visible).count(), 21);
 
-            let mut trees_up = 0;
-            let mut j_look:i32 = j as i32 - 1;
-            while j_look >= 0 {
-                trees_up = trees_up + 1;
-                if tree <= grid[j_look as usize][i] {
-                    break;
-                }
-                j_look = j_look - 1;
-            }
+        let (coords, best) = forest.best_tree(&views).unwrap();
+        assert_eq!(coords, (2, 3));
+        assert_eq!(best.sightlines, Sightlines { up: 2, down: 1, left: 2, right: 2 });
+        assert_eq!(best.sightlines.scenic_score(), 8);
 
-            let mut trees_down = 0;
-            j_look = j as i32 + 1;
-            while j_look < grid_size as i32 {
-                trees_down = trees_down + 1;
-                if tree <= grid[j_look as usize][i] {
-                    break;
-                }
-                j_look = j_look + 1;
-            }
+        // The middle 5 in the second row can be seen from the top and left only
+        let view = views[forest.idx(2, 1)];
+        assert!(view.visible);
+        assert_eq!(view.sightlines, Sightlines { up: 1, down: 2, left: 1, right: 2 });
+    }
 
-            let mut trees_left = 0;
-            let mut i_look:i32 = i as i32 - 1;
-            while i_look >= 0 {
-                trees_left = trees_left + 1;
-                if tree <= grid[j][i_look as usize] {
-                    break;
-                }
-                i_look = i_look - 1;
-            }
+    #[test]
+    fn day08_example_output() {
+        let input_data = fs::read_to_string("src/day08/example.txt").unwrap();
+        let forest = Forest::new(&input_data);
+        let views = forest.survey();
+        assert_eq!(forest.visibility_map(&views), "#####\n###.#\n##.##\n#.#.#\n#####");
+        assert_eq!(forest.scenic_heatmap(&views).lines().nth(3), Some(" :@+ "));
+        assert_eq!(forest.to_csv(&scenic_scores(&views)).lines().nth(1), Some("0,1,4,1,0"));
+        assert_eq!(forest.to_csv(&visibility_mask(&views)).lines().nth(1), Some("1,1,1,0,1"));
+        assert_eq!(best_tree_report(&forest, &views),
+            "Best tree at (2, 3) with height 5 - up 2, down 1, left 2, right 2, score 8");
 
-            let mut trees_right = 0;
-            let mut i_look:i32 = i as i32 + 1;
-            while i_look < grid_size as i32{
-                trees_right = trees_right + 1;
-                if tree <= grid[j][i_look as usize] {
-                    break;
-                }
-                i_look = i_look + 1;
-            }
+        let pgm = forest.to_pgm(&scenic_scores(&views));
+        assert!(pgm.starts_with(b"P5\n5 5\n255\n"));
+        assert_eq!(pgm.len(), 11 + 25);
+        assert_eq!(pgm[11 + forest.idx(2, 3)], 255);
+        let ppm = forest.to_ppm(&views);
+        assert!(ppm.starts_with(b"P6\n5 5\n255\n"));
+        assert_eq!(ppm.len(), 11 + 75);
+    }
 
-            let score = trees_up * trees_down * trees_left * trees_right;
-            if max < score {
-                max = score;
-            }
-        }
+    #[test]
+    fn day08_rectangular() {
+        let forest = Forest::new("1234\n1024\n4321");
+        assert_eq!((forest.width, forest.height), (4, 3));
+        let views = forest.survey();
+        assert_eq!(views.iter().filter(|view| !view.visible).count(), 1);
+        assert!(!views[forest.idx(1, 1)].visible);
+        assert_eq!(views[forest.idx(2, 1)].sightlines, Sightlines { up: 1, down: 1, left: 2, right: 1 });
+        assert_eq!(forest.best_tree(&views).unwrap().0, (2, 1));
+
+        let forest = Forest::new("3\n1\n2");
+        let views = forest.survey();
+        assert!(views.iter().all(|view| view.visible));
+        assert_eq!(views[1].sightlines, Sightlines { up: 1, down: 1, left: 0, right: 0 });
     }
-    println!("MAX {}", max)
-}
\ No newline at end of file
+}
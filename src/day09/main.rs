@@ -1,143 +1,565 @@
-use std::{fs, collections::HashSet};
+use std::{env, fs, collections::HashSet};
 use std::fmt;
+use serde_json::json;
 
-enum Dir { Up, Down, Left, Right }
+enum Dir { Up, Down, Left, Right, Forward, Back }
 
-struct Step{ d: Dir, steps: u16 }
+impl Dir {
+    fn delta(&self) -> Pos {
+        match self {
+            Dir::Up => Pos{x:0, y:1, z:0},
+            Dir::Down => Pos{x:0, y:-1, z:0},
+            Dir::Left => Pos{x:-1, y:0, z:0},
+            Dir::Right => Pos{x:1, y:0, z:0},
+            Dir::Forward => Pos{x:0, y:0, z:1},
+            Dir::Back => Pos{x:0, y:0, z:-1},
+        }
+    }
+}
+
+impl fmt::Display for Dir {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Dir::Up => write!(f, "Up"),
+            Dir::Down => write!(f, "Down"),
+            Dir::Left => write!(f, "Left"),
+            Dir::Right => write!(f, "Right"),
+            Dir::Forward => write!(f, "Forward"),
+            Dir::Back => write!(f, "Back"),
+        }
+    }
+}
+
+// A move of the head, diagonal moves combine a direction from more than one axis
+struct Step{ d: Vec<Dir>, steps: u32 }
 
 impl Step {
-    fn new(source: &str) -> Self {
-        let (dir_str, count_str) = source.split_once(" ").unwrap();
-        let count: u16 = count_str.parse().unwrap();
-        let dir = match dir_str {
-            "U" => Dir::Up,
-            "D" => Dir::Down,
-            "L" => Dir::Left,
-            "R" => Dir::Right,
-            _ => panic!("unrecognized direction")
-        };
-        Step { d:dir, steps: count }
-        
+    fn new(dir_str: &str, steps: u32) -> Result<Self, ParseErrorKind> {
+        let mut d: Vec<Dir> = Vec::new();
+        for c in dir_str.chars() {
+            let dir = match c {
+                'U' => Dir::Up,
+                'D' => Dir::Down,
+                'L' => Dir::Left,
+                'R' => Dir::Right,
+                'F' => Dir::Forward,
+                'B' => Dir::Back,
+                _ => return Err(ParseErrorKind::UnknownDirection(dir_str.to_string())),
+            };
+            // Only one direction per axis, so `UD` or `UU` are rejected
+            let delta = dir.delta();
+            if d.iter().map(|x| x.delta()).any(|x| x.x * delta.x != 0 || x.y * delta.y != 0 || x.z * delta.z != 0) {
+                return Err(ParseErrorKind::ConflictingDirections(dir_str.to_string()));
+            }
+            d.push(dir);
+        }
+        Ok(Step { d, steps })
+    }
+
+    fn delta(&self) -> Pos {
+        self.d.iter().map(|dir| dir.delta()).fold(Pos::default(), |acc, x| Pos {
+            x: acc.x + x.x,
+            y: acc.y + x.y,
+            z: acc.z + x.z,
+        })
     }
 }
 
 impl fmt::Display for Step {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self.d {
-            Dir::Up => write!(f, "Up {}", self.steps),
-            Dir::Down => write!(f, "Down {}", self.steps),
-            Dir::Left => write!(f, "Left {}", self.steps),
-            Dir::Right => write!(f, "Right {}", self.steps),
+        let names = self.d.iter().map(|dir| dir.to_string()).collect::<Vec<String>>();
+        write!(f, "{} {}", names.join("-"), self.steps)
+    }
+}
+
+enum Instruction {
+    Move(Step),
+    Repeat(u32, Vec<Instruction>),
+}
+
+#[derive(Debug, PartialEq)]
+enum ParseErrorKind {
+    UnknownDirection(String),
+    ConflictingDirections(String),
+    MissingCount,
+    InvalidNumber(String),
+    ExpectedRepeat,
+    Unexpected(char),
+    UnclosedBlock,
+    UnmatchedClose,
+}
+
+// Line and column start at 1
+#[derive(Debug, PartialEq)]
+struct ParseError {
+    line: usize,
+    column: usize,
+    kind: ParseErrorKind,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: ", self.line, self.column)?;
+        match &self.kind {
+            ParseErrorKind::UnknownDirection(dir) => write!(f, "unknown direction '{}'", dir),
+            ParseErrorKind::ConflictingDirections(dir) => write!(f, "'{}' uses the same axis twice", dir),
+            ParseErrorKind::MissingCount => write!(f, "expected a step count"),
+            ParseErrorKind::InvalidNumber(n) => write!(f, "'{}' is not a valid count", n),
+            ParseErrorKind::ExpectedRepeat => write!(f, "expected 'x(' after a repeat count"),
+            ParseErrorKind::Unexpected(c) => write!(f, "unexpected '{}'", c),
+            ParseErrorKind::UnclosedBlock => write!(f, "repeat block is never closed"),
+            ParseErrorKind::UnmatchedClose => write!(f, "')' without a repeat block"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Number(String),
+    Open,
+    Close,
+}
+
+// Splits the source into tokens with their line and column, dropping `#` comments
+fn tokenize(source: &str) -> Result<Vec<(Token, usize, usize)>, ParseError> {
+    let mut tokens = Vec::new();
+    for (line_idx, line) in source.lines().enumerate() {
+        let line = line.split('#').next().unwrap();
+        let mut chars = line.char_indices().peekable();
+        while let Some((col, c)) = chars.next() {
+            let token = match c {
+                c if c.is_whitespace() => continue,
+                '(' => Token::Open,
+                ')' => Token::Close,
+                c if c.is_ascii_alphanumeric() => {
+                    let is_digit = c.is_ascii_digit();
+                    let mut text = c.to_string();
+                    while let Some((_, next)) = chars.next_if(|(_, x)| x.is_ascii_alphanumeric() && x.is_ascii_digit() == is_digit) {
+                        text.push(next);
+                    }
+                    if is_digit { Token::Number(text) } else { Token::Word(text) }
+                }
+                c => return Err(ParseError { line: line_idx + 1, column: col + 1, kind: ParseErrorKind::Unexpected(c) }),
+            };
+            tokens.push((token, line_idx + 1, col + 1));
         }
     }
+    Ok(tokens)
 }
 
-#[derive(Debug, Clone, Copy, Eq, Hash, PartialEq)]
-struct Pos {x:i32, y:i32}
+// Parses a list of moves such as `R 4`, `UL 2` or `3x(R 4 U 2)`, one or more per line.
+// Anything after a `#` is a comment
+fn parse_program(source: &str) -> Result<Vec<Instruction>, ParseError> {
+    let tokens = tokenize(source)?;
+    let mut pos = 0;
+    let program = parse_block(&tokens, &mut pos)?;
+    match tokens.get(pos) {
+        Some((_, line, column)) => Err(ParseError { line: *line, column: *column, kind: ParseErrorKind::UnmatchedClose }),
+        None => Ok(program),
+    }
+}
+
+// Parses instructions up to a closing bracket or the end of the tokens
+fn parse_block(tokens: &[(Token, usize, usize)], pos: &mut usize) -> Result<Vec<Instruction>, ParseError> {
+    let mut block = Vec::new();
+    while let Some((token, line, column)) = tokens.get(*pos) {
+        let error = |kind| ParseError { line: *line, column: *column, kind };
+        let count = |text: &str| text.parse::<u32>().map_err(|_| error(ParseErrorKind::InvalidNumber(text.to_string())));
+        match token {
+            Token::Close => break,
+            Token::Open => return Err(error(ParseErrorKind::Unexpected('('))),
+            Token::Word(dir_str) => {
+                let steps = match tokens.get(*pos + 1) {
+                    Some((Token::Number(n), _, _)) => count(n)?,
+                    _ => return Err(error(ParseErrorKind::MissingCount)),
+                };
+                block.push(Instruction::Move(Step::new(dir_str, steps).map_err(error)?));
+                *pos += 2;
+            }
+            Token::Number(n) => {
+                let times = count(n)?;
+                match (tokens.get(*pos + 1), tokens.get(*pos + 2)) {
+                    (Some((Token::Word(x), _, _)), Some((Token::Open, _, _))) if x == "x" => (),
+                    _ => return Err(error(ParseErrorKind::ExpectedRepeat)),
+                }
+                *pos += 3;
+                let body = parse_block(tokens, pos)?;
+                if tokens.get(*pos).map(|(t, _, _)| t) != Some(&Token::Close) {
+                    return Err(error(ParseErrorKind::UnclosedBlock));
+                }
+                *pos += 1;
+                block.push(Instruction::Repeat(times, body));
+            }
+        }
+    }
+    Ok(block)
+}
+
+#[derive(Debug, Clone, Copy, Default, Eq, Hash, PartialEq)]
+struct Pos {x:i32, y:i32, z:i32}
 
 impl fmt::Display for Pos {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "[{},{}]", self.x, self.y)
+        write!(f, "[{},{},{}]", self.x, self.y, self.z)
     }
 }
 
 impl Pos {
-    fn next_to(&self, other: &Pos) -> bool {
-        let xdiff = (self.x - other.x).abs();
-        let ydiff = (self.y-other.y).abs();
-        xdiff <=1 && ydiff <=1
+    // Number of king moves between the two positions
+    fn distance(&self, other: &Pos) -> u32 {
+        self.x.abs_diff(other.x)
+            .max(self.y.abs_diff(other.y))
+            .max(self.z.abs_diff(other.z))
+    }
+
+    // Moves one step towards the other position along every axis where they differ
+    fn step_towards(&mut self, other: &Pos) {
+        self.x += (other.x - self.x).signum();
+        self.y += (other.y - self.y).signum();
+        self.z += (other.z - self.z).signum();
     }
 }
 
 struct Walker {
-    head : Pos,
-    tail: Vec<Pos>,
-    visited : HashSet<Pos>
+    knots: Vec<Pos>,
+    // How far each knot can be from the one in front before it is pulled along, the head's
+    // entry is not used
+    slack: Vec<u32>,
+    visited: Vec<HashSet<Pos>>,
+    // Position of every knot after each head move, starting with the initial position
+    trace: Option<Vec<Vec<Pos>>>,
+}
+
+// Area drawn by the renderers, inclusive on both ends
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Bounds { min_x: i32, max_x: i32, min_y: i32, max_y: i32 }
+
+impl Bounds {
+    fn around<'a, I: Iterator<Item = &'a Pos>>(positions: I) -> Bounds {
+        positions.fold(Bounds { min_x: 0, max_x: 0, min_y: 0, max_y: 0 }, |b, pos| Bounds {
+            min_x: b.min_x.min(pos.x),
+            max_x: b.max_x.max(pos.x),
+            min_y: b.min_y.min(pos.y),
+            max_y: b.max_y.max(pos.y),
+        })
+    }
+
+    // Draws rows from the top down, as y increases going up in the puzzle's diagrams. Only x
+    // and y are used so 3D ropes are seen from above
+    fn render<F: Fn(i32, i32) -> char>(&self, cell: F) -> String {
+        (self.min_y..=self.max_y).rev()
+            .map(|y| (self.min_x..=self.max_x).map(|x| cell(x, y)).collect::<String>())
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
 }
 
 impl Walker {
-    fn new(tail_len: u16) -> Walker {
-        let t: Vec<Pos> = (0..tail_len)
-            .map(|_| Pos{x:0, y:0})
+    fn new(tail_len: usize) -> Walker {
+        let knots = vec![Pos::default(); tail_len + 1];
+        let visited = knots.iter()
+            .map(|knot| HashSet::from([*knot]))
             .collect();
 
-        Walker { head: Pos{x: 0,y: 0}, tail: t, visited: HashSet::new() }
+        Walker { knots, slack: vec![1; tail_len + 1], visited, trace: None }
     }
 
-    fn move_head(&mut self, d: &Dir) {
-        match d {
-            Dir::Up => self.head.y += 1,
-            Dir::Down => self.head.y -= 1,
-            Dir::Left => self.head.x -= 1,
-            Dir::Right => self.head.x += 1,
-        }
+    fn with_trace(mut self) -> Walker {
+        self.trace = Some(vec![self.knots.clone()]);
+        self
+    }
+
+    fn set_slack(&mut self, knot: usize, slack: u32) {
+        self.slack[knot] = slack;
+    }
+
+    fn move_head(&mut self, delta: &Pos) {
+        self.knots[0].x += delta.x;
+        self.knots[0].y += delta.y;
+        self.knots[0].z += delta.z;
+        self.visited[0].insert(self.knots[0]);
     }
 
     fn move_tail(&mut self) {
-        let mut tail_idx = 0;
-        let mut seg_top = self.head;
-
-        while tail_idx < self.tail.len() {
-
-            if !seg_top.next_to(&self.tail[tail_idx]) {
-                let xdiff = seg_top.x - self.tail[tail_idx].x;
-                let ydiff = seg_top.y - self.tail[tail_idx].y;
-
-                if xdiff == 2 && ydiff == 2 {
-                    self.tail[tail_idx].x = seg_top.x - 1;
-                    self.tail[tail_idx].y = seg_top.y - 1;
-                } else if xdiff == -2 && ydiff == -2 {
-                    self.tail[tail_idx].x = seg_top.x + 1;
-                    self.tail[tail_idx].y = seg_top.y + 1;
-                } else if xdiff == 2 && ydiff == -2 {
-                    self.tail[tail_idx].x = seg_top.x - 1;
-                    self.tail[tail_idx].y = seg_top.y + 1;
-                } else if xdiff == -2 && ydiff == 2 {
-                    self.tail[tail_idx].x = seg_top.x + 1;
-                    self.tail[tail_idx].y = seg_top.y - 1;
-                } else if xdiff > 1 {
-                    self.tail[tail_idx].x = seg_top.x - 1;
-                    self.tail[tail_idx].y = seg_top.y
-                } else if xdiff < -1 {
-                    self.tail[tail_idx].x = seg_top.x + 1;
-                    self.tail[tail_idx].y = seg_top.y
-                } else if ydiff > 1 {
-                    self.tail[tail_idx].x = seg_top.x;
-                    self.tail[tail_idx].y = seg_top.y - 1
-                } else {
-                    self.tail[tail_idx].x = seg_top.x;
-                    self.tail[tail_idx].y = seg_top.y + 1
-                }
+        for idx in 1..self.knots.len() {
+            let leader = self.knots[idx - 1];
+            while self.knots[idx].distance(&leader) > self.slack[idx] {
+                self.knots[idx].step_towards(&leader);
+                self.visited[idx].insert(self.knots[idx]);
             }
-            seg_top = self.tail[tail_idx];
-            tail_idx += 1;
         }
-        self.visited.insert(self.tail[tail_idx-1].clone().to_owned());
-
     }
 
     fn walk(&mut self, action: &Step) {
+        let delta = action.delta();
         for _ in 0..action.steps {
-            self.move_head(&action.d);
+            self.move_head(&delta);
             self.move_tail();
+            if let Some(trace) = &mut self.trace {
+                trace.push(self.knots.clone());
+            }
         }
     }
+
+    fn run(&mut self, program: &[Instruction]) {
+        for instruction in program {
+            match instruction {
+                Instruction::Move(step) => self.walk(step),
+                Instruction::Repeat(times, body) => (0..*times).for_each(|_| self.run(body)),
+            }
+        }
+    }
+
+    fn tail_visited(&self) -> &HashSet<Pos> {
+        self.visited.last().unwrap()
+    }
+
+    fn trace(&self) -> &[Vec<Pos>] {
+        self.trace.as_deref().expect("walker was created without a trace")
+    }
+
+    // One row per knot per step, step 0 is the starting position
+    fn trace_csv(&self) -> String {
+        let mut csv = String::from("step,knot,x,y,z\n");
+        for (step, knots) in self.trace().iter().enumerate() {
+            for (knot, pos) in knots.iter().enumerate() {
+                csv.push_str(&format!("{},{},{},{},{}\n", step, knot, pos.x, pos.y, pos.z));
+            }
+        }
+        csv
+    }
+
+    // One JSON object per step holding the positions of all knots, head first
+    fn trace_json_lines(&self) -> String {
+        self.trace().iter()
+            .enumerate()
+            .map(|(step, knots)| json!({
+                "step": step,
+                "knots": knots.iter().map(|pos| [pos.x, pos.y, pos.z]).collect::<Vec<[i32; 3]>>(),
+            }).to_string() + "\n")
+            .collect()
+    }
+
+    fn bounds(&self) -> Bounds {
+        match &self.trace {
+            Some(trace) => Bounds::around(trace.iter().flatten()),
+            None => Bounds::around(self.visited.iter().flatten()),
+        }
+    }
+
+    // Cells the knot has been in as `#`, with the start shown as `s`
+    fn render_trail(&self, knot: usize, bounds: &Bounds) -> String {
+        let cells = self.visited[knot].iter()
+            .map(|pos| (pos.x, pos.y))
+            .collect::<HashSet<(i32, i32)>>();
+        bounds.render(|x, y| match (x, y) {
+            (0, 0) => 's',
+            _ if cells.contains(&(x, y)) => '#',
+            _ => '.',
+        })
+    }
+
+    // The rope after the given step, labelled like the puzzle: `H` for the head then `T` for a
+    // single tail or numbers for longer ropes. Knots nearer the head are drawn on top
+    fn render_rope(&self, step: usize, bounds: &Bounds) -> String {
+        let knots = &self.trace()[step];
+        let label = |idx: usize| match idx {
+            0 => 'H',
+            _ if knots.len() == 2 => 'T',
+            _ => char::from_digit(idx as u32 % 10, 10).unwrap(),
+        };
+        bounds.render(|x, y| {
+            match knots.iter().position(|pos| pos.x == x && pos.y == y) {
+                Some(idx) => label(idx),
+                None if (x, y) == (0, 0) => 's',
+                None => '.',
+            }
+        })
+    }
 }
 
 
+fn read_program(path: &str) -> Vec<Instruction> {
+    let input_data = fs::read_to_string(path).unwrap();
+    parse_program(&input_data).unwrap_or_else(|e| {
+        eprintln!("Invalid instructions in {} - {}", path, e);
+        std::process::exit(1);
+    })
+}
+
+fn traced_walk(path: &str) -> Walker {
+    let mut walker = Walker::new(9).with_trace();
+    walker.run(&read_program(path));
+    walker
+}
+
+// With no arguments this solves the puzzle, otherwise it dumps the 10 knot rope for a file:
+//   csv <file> | jsonl <file>  positions of every knot after each step
+//   trail <file>               cells visited by the tail
+//   rope <file> <step>         the rope after the given step
 fn main() {
-    let input_data = fs::read_to_string("src/day09/input.txt").unwrap();
-    let instructions:Vec<Step> = input_data.lines()
-        .map(|line| Step::new(line))
-        .collect();
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    match args.iter().map(String::as_str).collect::<Vec<&str>>().as_slice() {
+        ["csv", path] => print!("{}", traced_walk(path).trace_csv()),
+        ["jsonl", path] => print!("{}", traced_walk(path).trace_json_lines()),
+        ["trail", path] => {
+            let walker = traced_walk(path);
+            println!("{}", walker.render_trail(9, &walker.bounds()));
+        }
+        ["rope", path, step] => {
+            let walker = traced_walk(path);
+            match step.parse::<usize>() {
+                Ok(step) if step < walker.trace().len() => println!("{}", walker.render_rope(step, &walker.bounds())),
+                _ => eprintln!("Step must be a number from 0 to {}", walker.trace().len() - 1),
+            }
+        }
+        [] => solve(),
+        _ => eprintln!("Usage: day09 [csv <file> | jsonl <file> | trail <file> | rope <file> <step>]"),
+    }
+}
+
+fn solve() {
+    let instructions = read_program("src/day09/input.txt");
 
     let mut walker1 = Walker::new(1);
-    instructions.iter().for_each(|i| walker1.walk(i));
+    walker1.run(&instructions);
 
-    println!("PART 1: Visited coords - {}", walker1.visited.len());
+    println!("PART 1: Visited coords - {}", walker1.tail_visited().len());
 
     let mut walker2 = Walker::new(9);
-    instructions.iter().for_each(|i| walker2.walk(i));
-    
-    println!("PART 2: Visited coords - {}", walker2.visited.len());
-}
\ No newline at end of file
+    walker2.run(&instructions);
+
+    println!("PART 2: Visited coords - {}", walker2.tail_visited().len());
+
+    // Same rope, but the middle knot only gets pulled along once it is 3 away
+    let mut walker3 = Walker::new(9);
+    walker3.set_slack(5, 3);
+    walker3.run(&instructions);
+
+    println!("Slack rope: Visited coords per knot - {:?}",
+        walker3.visited.iter().map(|v| v.len()).collect::<Vec<usize>>());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn walk_file(path: &str, tail_len: usize) -> Walker {
+        let input_data = fs::read_to_string(path).unwrap();
+        let mut walker = Walker::new(tail_len);
+        walker.run(&parse_program(&input_data).unwrap());
+        walker
+    }
+
+    #[test]
+    fn day09_example() {
+        assert_eq!(walk_file("src/day09/example.txt", 1).tail_visited().len(), 13);
+        assert_eq!(walk_file("src/day09/example.txt", 9).tail_visited().len(), 1);
+        let walker = walk_file("src/day09/example2.txt", 9);
+        assert_eq!(walker.tail_visited().len(), 36);
+        assert_eq!(walker.visited.len(), 10);
+        assert_eq!(walker.knots[0], Pos{x:-11, y:15, z:0});
+    }
+
+    #[test]
+    fn day09_render_example() {
+        let input_data = fs::read_to_string("src/day09/example.txt").unwrap();
+        let mut walker = Walker::new(1).with_trace();
+        walker.run(&parse_program(&input_data).unwrap());
+        let bounds = Bounds { min_x: 0, max_x: 5, min_y: 0, max_y: 4 };
+        assert_eq!(walker.bounds(), bounds);
+        assert_eq!(walker.render_trail(1, &bounds), "..##..\n...##.\n.####.\n....#.\ns###..");
+        assert_eq!(walker.render_rope(0, &bounds), "......\n......\n......\n......\nH.....");
+        assert_eq!(walker.render_rope(3, &bounds), "......\n......\n......\n......\ns.TH..");
+        assert_eq!(walker.render_rope(24, &bounds), "......\n......\n.TH...\n......\ns.....");
+        assert_eq!(walker.trace().len(), 25);
+    }
+
+    #[test]
+    fn day09_render_long_rope() {
+        let input_data = fs::read_to_string("src/day09/example2.txt").unwrap();
+        let mut walker = Walker::new(9).with_trace();
+        walker.run(&parse_program(&input_data).unwrap());
+        let bounds = Bounds { min_x: -11, max_x: 14, min_y: -5, max_y: 15 };
+        let trail = walker.render_trail(9, &bounds);
+        assert_eq!(trail.lines().nth(9), Some("#........................."));
+        assert_eq!(trail.lines().nth(15), Some("....#......s.........#...."));
+        assert_eq!(trail.lines().nth(20), Some(".........########........."));
+
+        // After `R 5`, the head has pulled the first four knots along
+        let rope = walker.render_rope(5, &Bounds { min_x: -11, max_x: 14, min_y: -5, max_y: 15 });
+        assert_eq!(rope.lines().nth(15), Some("...........54321H........."));
+    }
+
+    #[test]
+    fn day09_trace_export() {
+        let mut walker = Walker::new(1).with_trace();
+        walker.run(&parse_program("R 2 UF 1").unwrap());
+        assert_eq!(walker.trace_csv(), "step,knot,x,y,z\n0,0,0,0,0\n0,1,0,0,0\n1,0,1,0,0\n1,1,0,0,0\n\
+            2,0,2,0,0\n2,1,1,0,0\n3,0,2,1,1\n3,1,1,0,0\n");
+        let lines = walker.trace_json_lines();
+        assert_eq!(lines.lines().last(), Some(r#"{"step":3,"knots":[[2,1,1],[1,0,0]]}"#));
+        assert_eq!(lines.lines().count(), 4);
+    }
+
+    #[test]
+    fn day09_three_dimensions() {
+        let mut walker = Walker::new(2);
+        walker.run(&parse_program("F 3").unwrap());
+        walker.run(&parse_program("U 2").unwrap());
+        assert_eq!(walker.knots, [Pos{x:0, y:2, z:3}, Pos{x:0, y:1, z:3}, Pos{x:0, y:1, z:2}]);
+        walker.run(&parse_program("B 4").unwrap());
+        assert_eq!(walker.knots, [Pos{x:0, y:2, z:-1}, Pos{x:0, y:2, z:0}, Pos{x:0, y:2, z:1}]);
+        assert_eq!(walker.visited[2].len(), 4);
+    }
+
+    #[test]
+    fn day09_diagonal_and_repeats() {
+        let program = parse_program("# warm up\nUL 2\n3x(R 4 U 2) # staircase\n2x(DR 1 2x(F 1))\n").unwrap();
+        let mut walker = Walker::new(1);
+        walker.run(&program);
+        assert_eq!(walker.knots[0], Pos{x:12, y:6, z:4});
+        assert_eq!(program.len(), 3);
+        match &program[0] {
+            Instruction::Move(step) => assert_eq!(step.to_string(), "Up-Left 2"),
+            Instruction::Repeat(..) => panic!("expected a move"),
+        }
+
+        // Several moves can share a line
+        let mut walker = Walker::new(1);
+        walker.run(&parse_program("R 4 U 4 L 3 D 1 R 4 D 1 L 5 R 2").unwrap());
+        assert_eq!(walker.tail_visited().len(), 13);
+    }
+
+    #[test]
+    fn day09_parse_errors() {
+        let error = |source: &str| parse_program(source).err().unwrap();
+        assert_eq!(error("R 4\nX 2"), ParseError { line: 2, column: 1, kind: ParseErrorKind::UnknownDirection("X".to_string()) });
+        assert_eq!(error("UD 2").kind, ParseErrorKind::ConflictingDirections("UD".to_string()));
+        assert_eq!(error("R").kind, ParseErrorKind::MissingCount);
+        assert_eq!(error("R 99999999999").kind, ParseErrorKind::InvalidNumber("99999999999".to_string()));
+        assert_eq!(error("3 R 4").kind, ParseErrorKind::ExpectedRepeat);
+        assert_eq!(error("2x(R 1\nU 1"), ParseError { line: 1, column: 1, kind: ParseErrorKind::UnclosedBlock });
+        assert_eq!(error("R 1 )"), ParseError { line: 1, column: 5, kind: ParseErrorKind::UnmatchedClose });
+        assert_eq!(error("R -1").to_string(), "1:3: unexpected '-'");
+    }
+
+    #[test]
+    fn day09_slack() {
+        let mut walker = Walker::new(2);
+        walker.set_slack(1, 3);
+        walker.set_slack(2, 0);
+        walker.run(&parse_program("R 5").unwrap());
+        assert_eq!(walker.knots, [Pos{x:5, y:0, z:0}, Pos{x:2, y:0, z:0}, Pos{x:2, y:0, z:0}]);
+        assert_eq!(walker.visited[1], walker.visited[2]);
+
+        // A leader several steps away is still followed one king move at a time
+        let mut walker = Walker::new(1);
+        walker.knots[0] = Pos{x:7, y:3, z:-2};
+        walker.move_tail();
+        assert_eq!(walker.knots[1], Pos{x:6, y:3, z:-2});
+        assert!(walker.visited[1].contains(&Pos{x:2, y:2, z:-2}));
+        assert_eq!(walker.visited[1].len(), 7);
+    }
+}
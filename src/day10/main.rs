@@ -1,109 +1,915 @@
-use std::{fs, collections::{HashMap, HashSet}};
+use std::{env, fmt, fs, collections::HashMap, io::{self, BufRead, BufReader, Write}};
 
-#[derive(PartialEq)]
-enum OpType {Noop, Addx}
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Registers {
+    x: i32,
+}
+
+// An instruction takes a fixed number of cycles and only changes the registers once the
+// last of those cycles has finished
+trait Instruction: fmt::Display {
+    fn cycles(&self) -> u32;
+    fn execute(&self, regs: &mut Registers);
+}
+
+struct Noop;
+
+impl Instruction for Noop {
+    fn cycles(&self) -> u32 { 1 }
+    fn execute(&self, _: &mut Registers) {}
+}
+
+impl fmt::Display for Noop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "noop")
+    }
+}
+
+struct Addx(i32);
+
+impl Instruction for Addx {
+    fn cycles(&self) -> u32 { 2 }
+    fn execute(&self, regs: &mut Registers) {
+        regs.x += self.0;
+    }
+}
+
+impl fmt::Display for Addx {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "addx {}", self.0)
+    }
+}
 
-struct Op {
-    op_type: OpType,
-    cycles: u8,
-    arg: Option<i32>,
+#[derive(Debug, PartialEq)]
+enum ParseError {
+    UnknownInstruction { line: usize, name: String },
+    InvalidArguments { line: usize, source: String },
 }
 
-impl Op {
-    fn parse(source: &str) -> Self {
-        let (op, arg) = source.split_once(" ").unwrap_or((source, ""));
-        match op {
-            "noop" => Op { op_type: OpType::Noop, cycles:1, arg: None},
-            "addx" => Op { op_type: OpType::Addx, cycles:2, arg: Some(arg.parse().unwrap())},
-            _ => panic!("unrecogonzied op")
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::UnknownInstruction { line, name } => write!(f, "line {}: unknown instruction '{}'", line, name),
+            ParseError::InvalidArguments { line, source } => write!(f, "line {}: invalid arguments in '{}'", line, source),
         }
     }
 }
 
-#[derive(Debug)]
-struct Comp {
-    cycle: i32,
-    prev_cycle: i32,
-    x : i32,
-    prev_x: i32,
-    watcher_idx: usize,
-    signals : HashMap<i32, i32>,
-    crt: HashSet<(i32,i32)>,
+// Builds an instruction from its arguments, or None if they are not valid
+type InstructionParser = fn(&[&str]) -> Option<Box<dyn Instruction>>;
+
+// Maps each mnemonic to the parser for that instruction, so new instructions can be added
+// without changing the CPU
+struct InstructionSet {
+    parsers: HashMap<&'static str, InstructionParser>,
 }
 
-impl Comp {
-    const SIGNAL: &'static [i32] = &[20, 60, 100, 140, 180, 220];
+impl InstructionSet {
+    fn standard() -> Self {
+        let mut set = InstructionSet { parsers: HashMap::new() };
+        set.register("noop", |args| match args {
+            [] => Some(Box::new(Noop)),
+            _ => None,
+        });
+        set.register("addx", |args| match args {
+            [v] => Some(Box::new(Addx(v.parse().ok()?))),
+            _ => None,
+        });
+        set
+    }
 
-    fn new() -> Self {
-        Comp { 
-            cycle: 0, 
-            prev_cycle:0, 
-            x: 1, 
-            prev_x: 1, 
-            watcher_idx: 0, 
-            signals: HashMap::new(),
-            crt: HashSet::new(),
-         }
+    fn register(&mut self, mnemonic: &'static str, parser: InstructionParser) {
+        self.parsers.insert(mnemonic, parser);
     }
 
-    fn run(&mut self, op: &Op) {
-        self.prev_cycle = self.cycle;
-        self.prev_x = self.x;
-        self.cycle += op.cycles as i32;
-        if op.op_type == OpType::Addx {
-            self.x += op.arg.unwrap();
-        }
+    // Line numbers in errors start at 1
+    fn parse(&self, source: &str) -> Result<Vec<Box<dyn Instruction>>, ParseError> {
+        source.lines()
+            .enumerate()
+            .filter(|(_, text)| !text.trim().is_empty())
+            .map(|(idx, text)| {
+                let mut words = text.split_whitespace();
+                let name = words.next().unwrap();
+                let args = words.collect::<Vec<&str>>();
+                let parser = self.parsers.get(name)
+                    .ok_or_else(|| ParseError::UnknownInstruction { line: idx + 1, name: name.to_string() })?;
+                parser(&args).ok_or_else(|| ParseError::InvalidArguments { line: idx + 1, source: text.to_string() })
+            })
+            .collect()
+    }
+}
 
-        // Record Signal Strengths
-        let sig = Self::SIGNAL.get(self.watcher_idx);
-        if sig.is_some() && self.cycle >= *sig.unwrap() {
-            self.signals.insert(Self::SIGNAL[self.watcher_idx], Self::SIGNAL[self.watcher_idx] * self.prev_x);
-            self.watcher_idx += 1;
-        }
+// What the CPU is doing during a cycle, the registers hold their values from the start of
+// the cycle
+struct Tick<'a> {
+    cycle: u32,
+    regs: &'a Registers,
+}
 
-        // Record Sprites
-        for cycle in self.prev_cycle..self.cycle {
-            let row = cycle / 40;
-            let column = cycle - (row * 40);
-            if (self.prev_x - column).abs() <= 1 {
-                self.crt.insert((row, column));
-            }
+trait Observer {
+    fn on_tick(&mut self, tick: &Tick);
+}
+
+struct Cpu {
+    regs: Registers,
+    cycle: u32,
+    program: Vec<Box<dyn Instruction>>,
+    pc: usize,
+    // Cycles already spent on the instruction at `pc`
+    elapsed: u32,
+}
+
+impl Cpu {
+    fn new(program: Vec<Box<dyn Instruction>>) -> Self {
+        Cpu { regs: Registers { x: 1 }, cycle: 0, program, pc: 0, elapsed: 0 }
+    }
+
+    // Runs a single cycle, returning false once there is nothing left to run
+    fn tick(&mut self, observers: &mut [&mut dyn Observer]) -> bool {
+        let Some(instruction) = self.program.get(self.pc) else {
+            return false;
+        };
+        self.cycle += 1;
+        let tick = Tick { cycle: self.cycle, regs: &self.regs };
+        observers.iter_mut().for_each(|observer| observer.on_tick(&tick));
+
+        self.elapsed += 1;
+        if self.elapsed >= instruction.cycles() {
+            instruction.execute(&mut self.regs);
+            self.pc += 1;
+            self.elapsed = 0;
         }
+        true
+    }
+
+    fn run(&mut self, observers: &mut [&mut dyn Observer]) {
+        while self.tick(observers) {}
+    }
+}
+
+// Records the signal strength (cycle number times X) during the chosen cycles
+struct SignalSampler {
+    sample_cycles: Vec<u32>,
+    signals: Vec<(u32, i32)>,
+}
+
+impl SignalSampler {
+    fn new(sample_cycles: Vec<u32>) -> Self {
+        SignalSampler { sample_cycles, signals: Vec::new() }
     }
 
     fn signal_strength(&self) -> i32 {
         self.signals.iter()
-            .map(|(_,v)| v)
+            .map(|(_, v)| v)
             .sum()
     }
+}
+
+impl Observer for SignalSampler {
+    fn on_tick(&mut self, tick: &Tick) {
+        if self.sample_cycles.contains(&tick.cycle) {
+            self.signals.push((tick.cycle, tick.cycle as i32 * tick.regs.x));
+        }
+    }
+}
+
+// Draws one pixel per cycle, left to right and top to bottom, wrapping back to the top
+// once the screen is full. A pixel is lit when the sprite centred on X covers it, an even
+// width sprite has the extra pixel to the right of X
+struct Crt {
+    width: usize,
+    height: usize,
+    sprite_width: usize,
+    pixels: Vec<bool>,
+}
+
+impl Crt {
+    fn new(width: usize, height: usize, sprite_width: usize) -> Self {
+        assert!(width > 0 && height > 0, "CRT must have at least one pixel");
+        Crt { width, height, sprite_width, pixels: vec![false; width * height] }
+    }
+
+    fn sprite_covers(&self, x: i32, column: i32) -> bool {
+        let left = x - (self.sprite_width as i32 - 1) / 2;
+        column >= left && column < left + self.sprite_width as i32
+    }
+
+    // Binary greyscale image, each pixel drawn as a `scale` by `scale` square
+    fn to_pgm(&self, scale: usize) -> Vec<u8> {
+        let mut image = format!("P5\n{} {}\n255\n", self.width * scale, self.height * scale).into_bytes();
+        for row in self.pixels.chunks(self.width) {
+            let line = row.iter()
+                .flat_map(|lit| std::iter::repeat_n(if *lit { 255 } else { 0 }, scale))
+                .collect::<Vec<u8>>();
+            (0..scale).for_each(|_| image.extend(&line));
+        }
+        image
+    }
+}
+
+impl Observer for Crt {
+    fn on_tick(&mut self, tick: &Tick) {
+        let pixel = (tick.cycle as usize - 1) % self.pixels.len();
+        let column = (pixel % self.width) as i32;
+        self.pixels[pixel] = self.sprite_covers(tick.regs.x, column);
+    }
+}
+
+impl fmt::Display for Crt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for row in self.pixels.chunks(self.width).take(self.height) {
+            let line = row.iter().map(|lit| if *lit { '#' } else { '.' }).collect::<String>();
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+// Capital letters as drawn on the CRT, 4 pixels wide and 6 high with a blank column between
+// letters. Only the letters that have been seen in puzzle answers are known
+const FONT: [(char, [&str; 6]); 17] = [
+    ('A', [".##.", "#..#", "#..#", "####", "#..#", "#..#"]),
+    ('B', ["###.", "#..#", "###.", "#..#", "#..#", "###."]),
+    ('C', [".##.", "#..#", "#...", "#...", "#..#", ".##."]),
+    ('E', ["####", "#...", "###.", "#...", "#...", "####"]),
+    ('F', ["####", "#...", "###.", "#...", "#...", "#..."]),
+    ('G', [".##.", "#..#", "#...", "#.##", "#..#", ".###"]),
+    ('H', ["#..#", "#..#", "####", "#..#", "#..#", "#..#"]),
+    ('I', [".###", "..#.", "..#.", "..#.", "..#.", ".###"]),
+    ('J', ["..##", "...#", "...#", "...#", "#..#", ".##."]),
+    ('K', ["#..#", "#.#.", "##..", "#.#.", "#.#.", "#..#"]),
+    ('L', ["#...", "#...", "#...", "#...", "#...", "####"]),
+    ('O', [".##.", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('P', ["###.", "#..#", "#..#", "###.", "#...", "#..."]),
+    ('R', ["###.", "#..#", "#..#", "###.", "#.#.", "#..#"]),
+    ('S', [".###", "#...", "#...", ".##.", "...#", "###."]),
+    ('U', ["#..#", "#..#", "#..#", "#..#", "#..#", ".##."]),
+    ('Z', ["####", "...#", "..#.", ".#..", "#...", "####"]),
+];
+const GLYPH_WIDTH: usize = 4;
+const GLYPH_HEIGHT: usize = 6;
+
+// Glyphs that did not match any letter, numbered from 0 in reading order. `text` has a `?`
+// in their place
+#[derive(Debug, PartialEq)]
+struct OcrError {
+    text: String,
+    unrecognised: Vec<usize>,
+}
+
+impl fmt::Display for OcrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let positions = self.unrecognised.iter().map(|p| p.to_string()).collect::<Vec<String>>();
+        write!(f, "unrecognised glyphs at {} in '{}'", positions.join(", "), self.text)
+    }
+}
+
+impl Crt {
+    fn is_lit(&self, row: usize, column: usize) -> bool {
+        self.pixels[row * self.width + column]
+    }
+
+    fn glyph(&self, top: usize, left: usize) -> Option<char> {
+        let matches = |rows: &[&str; 6]| rows.iter().enumerate().all(|(j, row)| {
+            row.chars().enumerate().all(|(i, c)| (c == '#') == self.is_lit(top + j, left + i))
+        });
+        // Anything in the gap after the letter means this is not a letter from the font
+        let gap_clear = left + GLYPH_WIDTH >= self.width
+            || (0..GLYPH_HEIGHT).all(|j| !self.is_lit(top + j, left + GLYPH_WIDTH));
+        if !gap_clear {
+            return None;
+        }
+        if (0..GLYPH_HEIGHT).all(|j| (0..GLYPH_WIDTH).all(|i| !self.is_lit(top + j, left + i))) {
+            return Some(' ');
+        }
+        FONT.iter().find(|(_, rows)| matches(rows)).map(|(c, _)| *c)
+    }
+
+    // Reads the screen as text, with a line of text for every 6 rows of pixels
+    fn read_text(&self) -> Result<String, OcrError> {
+        let mut lines = Vec::new();
+        let mut unrecognised = Vec::new();
+        let mut position = 0;
+        for top in (0..self.height - self.height % GLYPH_HEIGHT).step_by(GLYPH_HEIGHT) {
+            let mut line = String::new();
+            for left in (0..self.width).step_by(GLYPH_WIDTH + 1).filter(|left| left + GLYPH_WIDTH <= self.width) {
+                match self.glyph(top, left) {
+                    Some(c) => line.push(c),
+                    None => {
+                        line.push('?');
+                        unrecognised.push(position);
+                    }
+                }
+                position += 1;
+            }
+            lines.push(line.trim_end().to_string());
+        }
+        let text = lines.join("\n");
+        match unrecognised.is_empty() {
+            true => Ok(text),
+            false => Err(OcrError { text, unrecognised }),
+        }
+    }
+}
+
+const SIGNAL_CYCLES: [u32; 6] = [20, 60, 100, 140, 180, 220];
+const CRT_WIDTH: usize = 40;
+const CRT_HEIGHT: usize = 6;
+const SPRITE_WIDTH: usize = 3;
+
+// Expressions for debugger watches, over the X register and the cycle number. Comparisons
+// give 1 when true and 0 when false
+#[derive(Debug, PartialEq)]
+enum Expr {
+    X,
+    Cycle,
+    Num(i64),
+    Neg(Box<Expr>),
+    Binary(Box<Expr>, char, Box<Expr>),
+    Compare(Box<Expr>, &'static str, Box<Expr>),
+}
+
+impl Expr {
+    fn parse(source: &str) -> Result<Expr, String> {
+        let tokens = Expr::tokenize(source)?;
+        let mut pos = 0;
+        let expr = Expr::parse_compare(&tokens, &mut pos)?;
+        match tokens.get(pos) {
+            Some(token) => Err(format!("unexpected '{}'", token)),
+            None => Ok(expr),
+        }
+    }
+
+    fn tokenize(source: &str) -> Result<Vec<String>, String> {
+        let mut tokens: Vec<String> = Vec::new();
+        let mut chars = source.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                c if c.is_whitespace() => (),
+                c if c.is_ascii_alphanumeric() => {
+                    let mut word = c.to_string();
+                    while let Some(next) = chars.next_if(|x| x.is_ascii_alphanumeric()) {
+                        word.push(next);
+                    }
+                    tokens.push(word);
+                }
+                '<' | '>' | '=' | '!' => match chars.next_if_eq(&'=') {
+                    Some(_) => tokens.push(format!("{}=", c)),
+                    None if c == '<' || c == '>' => tokens.push(c.to_string()),
+                    None => return Err(format!("unexpected '{}'", c)),
+                },
+                '+' | '-' | '*' | '/' | '%' | '(' | ')' => tokens.push(c.to_string()),
+                _ => return Err(format!("unexpected '{}'", c)),
+            }
+        }
+        Ok(tokens)
+    }
+
+    fn parse_compare(tokens: &[String], pos: &mut usize) -> Result<Expr, String> {
+        let left = Expr::parse_sum(tokens, pos)?;
+        let op = match tokens.get(*pos).map(String::as_str) {
+            Some("<") => "<",
+            Some("<=") => "<=",
+            Some(">") => ">",
+            Some(">=") => ">=",
+            Some("==") => "==",
+            Some("!=") => "!=",
+            _ => return Ok(left),
+        };
+        *pos += 1;
+        let right = Expr::parse_sum(tokens, pos)?;
+        Ok(Expr::Compare(Box::new(left), op, Box::new(right)))
+    }
+
+    fn parse_sum(tokens: &[String], pos: &mut usize) -> Result<Expr, String> {
+        let mut expr = Expr::parse_product(tokens, pos)?;
+        while let Some(op) = tokens.get(*pos).filter(|t| *t == "+" || *t == "-") {
+            *pos += 1;
+            let right = Expr::parse_product(tokens, pos)?;
+            expr = Expr::Binary(Box::new(expr), op.chars().next().unwrap(), Box::new(right));
+        }
+        Ok(expr)
+    }
 
-    fn print_crt(&self) {
-        for j in 0..6 {
-            for i in 0..40 {
-                if self.crt.contains(&(j,i)) {
-                    print!("#")
-                } else {
-                    print!(".")
+    fn parse_product(tokens: &[String], pos: &mut usize) -> Result<Expr, String> {
+        let mut expr = Expr::parse_atom(tokens, pos)?;
+        while let Some(op) = tokens.get(*pos).filter(|t| *t == "*" || *t == "/" || *t == "%") {
+            *pos += 1;
+            let right = Expr::parse_atom(tokens, pos)?;
+            expr = Expr::Binary(Box::new(expr), op.chars().next().unwrap(), Box::new(right));
+        }
+        Ok(expr)
+    }
+
+    fn parse_atom(tokens: &[String], pos: &mut usize) -> Result<Expr, String> {
+        let token = tokens.get(*pos).ok_or("unexpected end of expression")?;
+        *pos += 1;
+        match token.as_str() {
+            "x" | "X" => Ok(Expr::X),
+            "cycle" => Ok(Expr::Cycle),
+            "-" => Ok(Expr::Neg(Box::new(Expr::parse_atom(tokens, pos)?))),
+            "(" => {
+                let expr = Expr::parse_compare(tokens, pos)?;
+                match tokens.get(*pos).map(String::as_str) {
+                    Some(")") => {
+                        *pos += 1;
+                        Ok(expr)
+                    }
+                    _ => Err(String::from("missing ')'")),
+                }
+            }
+            n => n.parse().map(Expr::Num).map_err(|_| format!("unexpected '{}'", n)),
+        }
+    }
+
+    // Division by zero gives None rather than stopping the debugger
+    fn eval(&self, x: i32, cycle: u32) -> Option<i64> {
+        match self {
+            Expr::X => Some(x as i64),
+            Expr::Cycle => Some(cycle as i64),
+            Expr::Num(n) => Some(*n),
+            Expr::Neg(e) => e.eval(x, cycle)?.checked_neg(),
+            Expr::Binary(l, op, r) => {
+                let (l, r) = (l.eval(x, cycle)?, r.eval(x, cycle)?);
+                match op {
+                    '+' => l.checked_add(r),
+                    '-' => l.checked_sub(r),
+                    '*' => l.checked_mul(r),
+                    '/' => l.checked_div(r),
+                    _ => l.checked_rem(r),
+                }
+            }
+            Expr::Compare(l, op, r) => {
+                let (l, r) = (l.eval(x, cycle)?, r.eval(x, cycle)?);
+                let result = match *op {
+                    "<" => l < r,
+                    "<=" => l <= r,
+                    ">" => l > r,
+                    ">=" => l >= r,
+                    "==" => l == r,
+                    _ => l != r,
+                };
+                Some(result as i64)
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Breakpoint {
+    // Stop before this cycle starts
+    Cycle(u32),
+    // Stop before the first cycle in which X has this value, after holding something else
+    X(i32),
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Breakpoint::Cycle(cycle) => write!(f, "cycle {}", cycle),
+            Breakpoint::X(x) => write!(f, "x {}", x),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct TraceRow {
+    cycle: u32,
+    instruction: String,
+    x_during: i32,
+    x_after: i32,
+}
+
+// Runs a CPU one cycle at a time, stopping at breakpoints and keeping a trace of every cycle
+struct Debugger {
+    cpu: Cpu,
+    breakpoints: Vec<Breakpoint>,
+    watches: Vec<(String, Expr)>,
+    trace: Vec<TraceRow>,
+}
+
+impl Debugger {
+    fn new(cpu: Cpu) -> Self {
+        Debugger { cpu, breakpoints: Vec::new(), watches: Vec::new(), trace: Vec::new() }
+    }
+
+    fn step(&mut self, observers: &mut [&mut dyn Observer]) -> bool {
+        let Some(instruction) = self.cpu.program.get(self.cpu.pc).map(|i| i.to_string()) else {
+            return false;
+        };
+        let x_during = self.cpu.regs.x;
+        self.cpu.tick(observers);
+        self.trace.push(TraceRow { cycle: self.cpu.cycle, instruction, x_during, x_after: self.cpu.regs.x });
+        true
+    }
+
+    // The breakpoint that applies to the cycle about to run, if any
+    fn breakpoint(&self) -> Option<&Breakpoint> {
+        let prev_x = self.trace.last().map(|row| row.x_during);
+        self.breakpoints.iter().find(|bp| match bp {
+            Breakpoint::Cycle(cycle) => *cycle == self.cpu.cycle + 1,
+            Breakpoint::X(x) => self.cpu.regs.x == *x && prev_x != Some(*x),
+        })
+    }
+
+    // Runs until a breakpoint or the end of the program, always running at least one cycle
+    // so it can be used to carry on from a breakpoint
+    fn resume(&mut self, observers: &mut [&mut dyn Observer]) -> Option<&Breakpoint> {
+        if !self.step(observers) {
+            return None;
+        }
+        while self.breakpoint().is_none() {
+            if !self.step(observers) {
+                return None;
+            }
+        }
+        self.breakpoint()
+    }
+
+    // State as seen during the cycle about to run, matching how breakpoints and the puzzle
+    // count cycles
+    fn status(&self) -> String {
+        let cycle = self.cpu.cycle + 1;
+        let next = match self.cpu.program.get(self.cpu.pc) {
+            Some(instruction) => format!("{} ({}/{})", instruction, self.cpu.elapsed + 1, instruction.cycles()),
+            None => String::from("halted"),
+        };
+        let mut status = format!("cycle {} x {} next {}", cycle, self.cpu.regs.x, next);
+        for (source, expr) in self.watches.iter() {
+            match expr.eval(self.cpu.regs.x, cycle) {
+                Some(value) => status.push_str(&format!("\n  {} = {}", source, value)),
+                None => status.push_str(&format!("\n  {} = error", source)),
+            }
+        }
+        status
+    }
+
+    fn trace_table(&self) -> String {
+        let mut table = format!("{:>6}  {:<12} {:>8} {:>8}\n", "cycle", "instruction", "x during", "x after");
+        for row in self.trace.iter() {
+            table.push_str(&format!("{:>6}  {:<12} {:>8} {:>8}\n", row.cycle, row.instruction, row.x_during, row.x_after));
+        }
+        table
+    }
+
+    // Runs debugger commands, one per line:
+    //   break cycle <n> | break x <n>  add a breakpoint
+    //   clear                          remove all breakpoints
+    //   watch <expr>                   show an expression over x and cycle after each stop
+    //   step [n]                       run n cycles, 1 by default
+    //   continue                       run to the next breakpoint
+    //   print | trace                  show the current state or every cycle so far
+    //   quit
+    fn run_commands<R: BufRead, W: Write>(&mut self, input: R, output: &mut W, observers: &mut [&mut dyn Observer]) -> io::Result<()> {
+        for line in input.lines() {
+            let line = line?;
+            let words = line.split_whitespace().collect::<Vec<&str>>();
+            match words.as_slice() {
+                [] => continue,
+                ["break", "cycle", n] | ["break", "x", n] => {
+                    let breakpoint = match words[1] {
+                        "cycle" => n.parse::<u32>().ok().filter(|n| *n > 0).map(Breakpoint::Cycle),
+                        _ => n.parse::<i32>().ok().map(Breakpoint::X),
+                    };
+                    let breakpoint = match breakpoint {
+                        Some(breakpoint) => breakpoint,
+                        None => {
+                            writeln!(output, "invalid breakpoint '{}'", n)?;
+                            continue;
+                        }
+                    };
+                    writeln!(output, "breakpoint at {}", breakpoint)?;
+                    self.breakpoints.push(breakpoint);
+                }
+                ["clear"] => self.breakpoints.clear(),
+                ["watch", ..] => {
+                    let source = line.trim_start().strip_prefix("watch").unwrap().trim();
+                    match Expr::parse(source) {
+                        Ok(expr) => self.watches.push((source.to_string(), expr)),
+                        Err(e) => writeln!(output, "invalid watch '{}': {}", source, e)?,
+                    }
+                }
+                ["step"] | ["step", _] => {
+                    let count = words.get(1).map_or(Ok(1), |n| n.parse::<u32>());
+                    match count {
+                        Ok(count) => {
+                            for _ in 0..count {
+                                if !self.step(observers) {
+                                    break;
+                                }
+                            }
+                            writeln!(output, "{}", self.status())?;
+                        }
+                        Err(_) => writeln!(output, "invalid step count '{}'", words[1])?,
+                    }
                 }
+                ["continue"] => {
+                    match self.resume(observers) {
+                        Some(breakpoint) => writeln!(output, "stopped at {}", breakpoint)?,
+                        None => writeln!(output, "program finished")?,
+                    }
+                    writeln!(output, "{}", self.status())?;
+                }
+                ["print"] => writeln!(output, "{}", self.status())?,
+                ["trace"] => write!(output, "{}", self.trace_table())?,
+                ["quit"] => break,
+                _ => writeln!(output, "unknown command '{}'", line.trim())?,
             }
-            println!();
         }
+        Ok(())
     }
 }
 
+fn read_program(path: &str) -> Vec<Box<dyn Instruction>> {
+    let input_data = fs::read_to_string(path).unwrap();
+    InstructionSet::standard().parse(&input_data).unwrap_or_else(|e| {
+        eprintln!("Invalid program in {} - {}", path, e);
+        std::process::exit(1);
+    })
+}
+
+fn draw_crt(path: &str, width: usize, height: usize, sprite_width: usize) -> Crt {
+    let mut crt = Crt::new(width, height, sprite_width);
+    Cpu::new(read_program(path)).run(&mut [&mut crt]);
+    crt
+}
+
+// With no arguments this solves the puzzle, otherwise:
+//   debug <program> [script]                      debug a program, reading commands from the
+//                                                 script or from stdin
+//   crt <program> <width> <height> <sprite width> [<file> [scale]]
+//                                                 draw a program on a custom screen, or save
+//                                                 it as a PGM image
+//   image <program> <file> [scale]                save the standard screen as a PGM image
 fn main() {
-    let input_data = fs::read_to_string("src/day10/input.txt").unwrap();
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    match args.iter().map(String::as_str).collect::<Vec<&str>>().as_slice() {
+        ["debug", path] => {
+            let mut debugger = Debugger::new(Cpu::new(read_program(path)));
+            debugger.run_commands(io::stdin().lock(), &mut io::stdout(), &mut []).unwrap();
+        }
+        ["debug", path, script] => {
+            let mut debugger = Debugger::new(Cpu::new(read_program(path)));
+            let script = BufReader::new(fs::File::open(script).unwrap());
+            debugger.run_commands(script, &mut io::stdout(), &mut []).unwrap();
+        }
+        ["crt", path, width, height, sprite_width, output @ ..] if output.len() <= 2 => {
+            let crt = draw_crt(path, width.parse().unwrap(), height.parse().unwrap(), sprite_width.parse().unwrap());
+            match output {
+                [] => print!("{}", crt),
+                [file, scale @ ..] => {
+                    let scale = scale.first().map_or(1, |scale| scale.parse().unwrap());
+                    fs::write(file, crt.to_pgm(scale)).unwrap();
+                }
+            }
+        }
+        ["image", path, file, scale @ ..] if scale.len() <= 1 => {
+            let scale = scale.first().map_or(1, |scale| scale.parse().unwrap());
+            fs::write(file, draw_crt(path, CRT_WIDTH, CRT_HEIGHT, SPRITE_WIDTH).to_pgm(scale)).unwrap();
+        }
+        [] => solve(),
+        _ => eprintln!("Usage: day10 [debug <program> [script] | crt <program> <width> <height> <sprite width> [<file> [scale]] | image <program> <file> [scale]]"),
+    }
+}
+
+fn solve() {
+    let program = read_program("src/day10/input.txt");
+
+    let mut sampler = SignalSampler::new(SIGNAL_CYCLES.to_vec());
+    let mut crt = Crt::new(CRT_WIDTH, CRT_HEIGHT, SPRITE_WIDTH);
+    let mut cpu = Cpu::new(program);
+    cpu.run(&mut [&mut sampler, &mut crt]);
 
-    let ops: Vec<Op> = input_data.lines()
-        .map(|line| Op::parse(line))
-        .collect();
+    println!("Part 1 - Signal Strength: {}", sampler.signal_strength());
+    match crt.read_text() {
+        Ok(text) => println!("Part 2 - Code: {}", text),
+        Err(e) => println!("Part 2 - Could not read the screen, {}", e),
+    }
+    print!("{}", crt);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_example(observers: &mut [&mut dyn Observer]) -> Cpu {
+        let input_data = fs::read_to_string("src/day10/example.txt").unwrap();
+        let mut cpu = Cpu::new(InstructionSet::standard().parse(&input_data).unwrap());
+        cpu.run(observers);
+        cpu
+    }
+
+    #[test]
+    fn day10_example() {
+        let mut sampler = SignalSampler::new(SIGNAL_CYCLES.to_vec());
+        let mut crt = Crt::new(CRT_WIDTH, CRT_HEIGHT, SPRITE_WIDTH);
+        let cpu = run_example(&mut [&mut sampler, &mut crt]);
+        assert_eq!(cpu.pc, cpu.program.len());
+        assert_eq!(cpu.cycle, 240);
+        assert_eq!(sampler.signals, [(20, 420), (60, 1140), (100, 1800), (140, 2940), (180, 2880), (220, 3960)]);
+        assert_eq!(sampler.signal_strength(), 13140);
+        assert_eq!(crt.to_string(), [
+            "##..##..##..##..##..##..##..##..##..##..",
+            "###...###...###...###...###...###...###.",
+            "####....####....####....####....####....",
+            "#####.....#####.....#####.....#####.....",
+            "######......######......######......####",
+            "#######.......#######.......#######.....",
+            "",
+        ].join("\n"));
+    }
+
+    fn draw(rows: &[&str]) -> Crt {
+        let mut crt = Crt::new(rows[0].len(), rows.len(), SPRITE_WIDTH);
+        crt.pixels = rows.iter().flat_map(|row| row.chars().map(|c| c == '#')).collect();
+        crt
+    }
 
-    let mut cmp = Comp::new();
+    #[test]
+    fn day10_read_text() {
+        let crt = draw(&[
+            "####.###...##..###..#..#.####..##..#..#.",
+            "#....#..#.#..#.#..#.#..#.#....#..#.#..#.",
+            "###..#..#.#....#..#.####.###..#....####.",
+            "#....###..#.##.###..#..#.#....#.##.#..#.",
+            "#....#....#..#.#....#..#.#....#..#.#..#.",
+            "#....#.....###.#....#..#.#.....###.#..#.",
+        ]);
+        assert_eq!(crt.read_text(), Ok("FPGPHFGH".to_string()));
 
-    ops.iter().for_each(|x| cmp.run(x));
+        let crt = draw(&[
+            ".##..###...##..",
+            "#..#.#..#.#..#.",
+            "#..#.###..#....",
+            "####.#..#.#....",
+            "#..#.#..#.#..#.",
+            "#..#.###...##..",
+            "..##.#..#.#....",
+            "...#.#.#..#....",
+            "...#.##...#....",
+            "...#.#.#..#....",
+            "#..#.#.#..#....",
+            ".##..#..#.####.",
+        ]);
+        assert_eq!(crt.read_text(), Ok("ABC\nJKL".to_string()));
+    }
+
+    #[test]
+    fn day10_unreadable_text() {
+        let mut crt = Crt::new(CRT_WIDTH, CRT_HEIGHT, SPRITE_WIDTH);
+        run_example(&mut [&mut crt]);
+        let err = crt.read_text().unwrap_err();
+        assert_eq!(err.unrecognised, (0..8).collect::<Vec<usize>>());
+        assert_eq!(err.text, "????????");
 
-    println!("Part 1 - Signal Strength: {}", cmp.signal_strength());
-    println!("Part 2");
-    cmp.print_crt()
+        let crt = draw(&[
+            ".##..#..#......####.",
+            "#..#..##..........#.",
+            "#..#.#..#........#..",
+            "####..##........#...",
+            "#..#.#..#......#....",
+            "#..#..##.......####.",
+        ]);
+        let err = crt.read_text().unwrap_err();
+        assert_eq!(err.unrecognised, [1]);
+        assert_eq!(err.to_string(), "unrecognised glyphs at 1 in 'A? Z'");
+    }
 
-}
\ No newline at end of file
+    #[test]
+    fn day10_debugger_script() {
+        let input_data = fs::read_to_string("src/day10/example.txt").unwrap();
+        let mut debugger = Debugger::new(Cpu::new(InstructionSet::standard().parse(&input_data).unwrap()));
+        let script = "break cycle 20\nwatch x * cycle\nwatch x > 20\ncontinue\nstep 2\nbreak x -1\ncontinue\nclear\ncontinue\n";
+        let mut output = Vec::new();
+        let mut sampler = SignalSampler::new(SIGNAL_CYCLES.to_vec());
+        debugger.run_commands(script.as_bytes(), &mut output, &mut [&mut sampler]).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.lines().collect::<Vec<&str>>(), [
+            "breakpoint at cycle 20",
+            "stopped at cycle 20",
+            "cycle 20 x 21 next addx -1 (1/2)",
+            "  x * cycle = 420",
+            "  x > 20 = 1",
+            "cycle 22 x 20 next addx 5 (1/2)",
+            "  x * cycle = 440",
+            "  x > 20 = 0",
+            "breakpoint at x -1",
+            "stopped at x -1",
+            "cycle 210 x -1 next addx 22 (1/2)",
+            "  x * cycle = -210",
+            "  x > 20 = 0",
+            "program finished",
+            "cycle 241 x 17 next halted",
+            "  x * cycle = 4097",
+            "  x > 20 = 0",
+        ]);
+        assert_eq!(sampler.signal_strength(), 13140);
+        assert_eq!(debugger.trace.len(), 240);
+        assert_eq!(debugger.trace[19], TraceRow { cycle: 20, instruction: "addx -1".to_string(), x_during: 21, x_after: 21 });
+        assert_eq!(debugger.trace[20], TraceRow { cycle: 21, instruction: "addx -1".to_string(), x_during: 21, x_after: 20 });
+    }
+
+    #[test]
+    fn day10_debugger_trace() {
+        let mut debugger = Debugger::new(Cpu::new(InstructionSet::standard().parse("noop\naddx 3\naddx -5").unwrap()));
+        let mut output = Vec::new();
+        debugger.run_commands("step 9\ntrace\nbogus\nwatch x +\nbreak cycle 0\nbreak cycle 4294967296\nbreak x 2147483648".as_bytes(), &mut output, &mut []).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), [
+            "cycle 6 x -1 next halted",
+            " cycle  instruction  x during  x after",
+            "     1  noop                1        1",
+            "     2  addx 3              1        1",
+            "     3  addx 3              1        4",
+            "     4  addx -5             4        4",
+            "     5  addx -5             4       -1",
+            "unknown command 'bogus'",
+            "invalid watch 'x +': unexpected end of expression",
+            "invalid breakpoint '0'",
+            "invalid breakpoint '4294967296'",
+            "invalid breakpoint '2147483648'",
+            "",
+        ].join("\n"));
+    }
+
+    #[test]
+    fn day10_watch_expressions() {
+        let eval = |source: &str| Expr::parse(source).unwrap().eval(7, 3);
+        assert_eq!(eval("x"), Some(7));
+        assert_eq!(eval("-x + 2 * (cycle - 1)"), Some(-3));
+        assert_eq!(eval("x % 4 == 3"), Some(1));
+        assert_eq!(eval("x / (cycle - 3)"), None);
+        assert_eq!(eval("x * 9999999999 * 9999999999"), None);
+        assert_eq!(eval("-(0 - 9223372036854775807 - 1)"), None);
+        assert_eq!(Expr::parse("x ! 1"), Err("unexpected '!'".to_string()));
+        assert_eq!(Expr::parse("(x"), Err("missing ')'".to_string()));
+    }
+
+    #[test]
+    fn day10_crt_geometry() {
+        let mut crt = Crt::new(20, 3, 1);
+        run_example(&mut [&mut crt]);
+        assert_eq!(crt.to_string(), [
+            "....................",
+            ".##..#..........#.#.",
+            ".................#..",
+            "",
+        ].join("\n"));
+
+        let program = "addx 2\nnoop\naddx 3\naddx -5\nnoop";
+        let mut crt = Crt::new(4, 2, 4);
+        Cpu::new(InstructionSet::standard().parse(program).unwrap()).run(&mut [&mut crt]);
+        assert_eq!(crt.to_string(), "####\n...#\n");
+        assert!(crt.sprite_covers(5, 4) && crt.sprite_covers(5, 7) && !crt.sprite_covers(5, 3) && !crt.sprite_covers(5, 8));
+    }
+
+    #[test]
+    fn day10_crt_image() {
+        let mut crt = Crt::new(3, 2, 1);
+        crt.pixels = vec![true, false, false, false, false, true];
+        assert_eq!(crt.to_pgm(1), b"P5\n3 2\n255\n\xff\x00\x00\x00\x00\xff");
+        let image = crt.to_pgm(2);
+        assert!(image.starts_with(b"P5\n6 4\n255\n"));
+        assert_eq!(&image[11..], [255, 255, 0, 0, 0, 0, 255, 255, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 0, 0, 0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn day10_sample_cycles() {
+        let mut sampler = SignalSampler::new(vec![1, 2, 3]);
+        run_example(&mut [&mut sampler]);
+        assert_eq!(sampler.signals, [(1, 1), (2, 2), (3, 48)]);
+    }
+
+    struct Mulx(i32);
+
+    impl Instruction for Mulx {
+        fn cycles(&self) -> u32 { 4 }
+        fn execute(&self, regs: &mut Registers) {
+            regs.x *= self.0;
+        }
+    }
+
+    impl fmt::Display for Mulx {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "mulx {}", self.0)
+        }
+    }
+
+    #[test]
+    fn day10_custom_instruction() {
+        let mut set = InstructionSet::standard();
+        set.register("mulx", |args| match args {
+            [v] => Some(Box::new(Mulx(v.parse().ok()?))),
+            _ => None,
+        });
+        let mut cpu = Cpu::new(set.parse("addx 2\nmulx 5\nnoop").unwrap());
+        let mut sampler = SignalSampler::new(vec![3, 6, 7]);
+        cpu.run(&mut [&mut sampler]);
+        assert_eq!(sampler.signals, [(3, 9), (6, 18), (7, 105)]);
+        assert_eq!(cpu.regs.x, 15);
+
+        assert_eq!(InstructionSet::standard().parse("noop\nmulx 5").err(),
+            Some(ParseError::UnknownInstruction { line: 2, name: "mulx".to_string() }));
+        assert_eq!(InstructionSet::standard().parse("addx\n").err(),
+            Some(ParseError::InvalidArguments { line: 1, source: "addx".to_string() }));
+    }
+}
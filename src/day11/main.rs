@@ -1,7 +1,11 @@
+use std::{env, fmt, fs};
+use std::collections::HashMap;
+use std::iter::Peekable;
+use rayon::prelude::*;
 
 struct Monkey {
     items: Vec<i128>,
-    op: fn(i128) -> i128,
+    op: Expr,
     count: i128,
     test: i128,
     test_passed: usize,
@@ -9,59 +13,294 @@ struct Monkey {
 }
 
 impl Monkey {
-    fn new(items: Vec<i128>, op: fn(i128) -> i128, test: i128, p: usize, f:usize) -> Self {
-        Monkey { items: items, op: op, count: 0, test: test, test_passed: p, test_failed: f }
+    fn new(items: Vec<i128>, op: Expr, test: i128, p: usize, f:usize) -> Self {
+        Monkey { items, op, count: 0, test, test_passed: p, test_failed: f }
     }
 }
 
-fn sq(x: i128, m: i128) -> i128 {
-    mult(x, x, m)
+// The right hand side of a monkey's `new = ...` operation
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    Old,
+    Num(i128),
+    Add(Box<Expr>, Box<Expr>),
+    Mult(Box<Expr>, Box<Expr>),
 }
 
-fn mult(x: i128, y: i128, m: i128) -> i128 {
-    ((x % m) * (y % m)) % m
+impl Expr {
+    // Evaluates with every intermediate result reduced modulo `modulus`, if there is one
+    fn eval(&self, old: i128, modulus: Option<i128>) -> i128 {
+        match (self, modulus) {
+            (Expr::Old, _) => modulus.map_or(old, |m| old % m),
+            (Expr::Num(n), _) => modulus.map_or(*n, |m| n % m),
+            (Expr::Add(a, b), Some(m)) => add_mod(a.eval(old, modulus), b.eval(old, modulus), m),
+            (Expr::Mult(a, b), Some(m)) => mul_mod(a.eval(old, modulus), b.eval(old, modulus), m),
+            (Expr::Add(a, b), None) => a.eval(old, None).checked_add(b.eval(old, None)).expect("Worry level overflowed"),
+            (Expr::Mult(a, b), None) => a.eval(old, None).checked_mul(b.eval(old, None)).expect("Worry level overflowed"),
+        }
+    }
+
+    // Folds constants and drops `+ 0` and `* 1`. Constants that overflow when folded are left
+    // as they are
+    fn simplify(&self) -> Expr {
+        match self {
+            Expr::Old | Expr::Num(_) => self.clone(),
+            Expr::Add(a, b) => match (a.simplify(), b.simplify()) {
+                (Expr::Num(x), Expr::Num(y)) if x.checked_add(y).is_some() => Expr::Num(x + y),
+                (Expr::Num(0), e) | (e, Expr::Num(0)) => e,
+                (a, b) => Expr::Add(Box::new(a), Box::new(b)),
+            },
+            Expr::Mult(a, b) => match (a.simplify(), b.simplify()) {
+                (Expr::Num(x), Expr::Num(y)) if x.checked_mul(y).is_some() => Expr::Num(x * y),
+                (Expr::Num(0), _) | (_, Expr::Num(0)) => Expr::Num(0),
+                (Expr::Num(1), e) | (e, Expr::Num(1)) => e,
+                (a, b) => Expr::Mult(Box::new(a), Box::new(b)),
+            },
+        }
+    }
+
+    fn parse(source: &str) -> Result<Expr, String> {
+        let tokens = source.replace('(', " ( ").replace(')', " ) ");
+        let mut tokens = tokens.split_whitespace().peekable();
+        let expr = Expr::parse_sum(&mut tokens)?;
+        match tokens.next() {
+            Some(token) => Err(format!("unexpected '{}'", token)),
+            None => Ok(expr),
+        }
+    }
+
+    fn parse_sum<'a>(tokens: &mut Peekable<impl Iterator<Item = &'a str>>) -> Result<Expr, String> {
+        let mut expr = Expr::parse_product(tokens)?;
+        while tokens.next_if_eq(&"+").is_some() {
+            expr = Expr::Add(Box::new(expr), Box::new(Expr::parse_product(tokens)?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_product<'a>(tokens: &mut Peekable<impl Iterator<Item = &'a str>>) -> Result<Expr, String> {
+        let mut expr = Expr::parse_term(tokens)?;
+        while tokens.next_if_eq(&"*").is_some() {
+            expr = Expr::Mult(Box::new(expr), Box::new(Expr::parse_term(tokens)?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_term<'a>(tokens: &mut Peekable<impl Iterator<Item = &'a str>>) -> Result<Expr, String> {
+        match tokens.next() {
+            Some("old") => Ok(Expr::Old),
+            Some("(") => {
+                let expr = Expr::parse_sum(tokens)?;
+                match tokens.next() {
+                    Some(")") => Ok(expr),
+                    _ => Err(String::from("missing ')'")),
+                }
+            }
+            Some(token) => token.parse::<u64>()
+                .map(|n| Expr::Num(n as i128))
+                .map_err(|_| format!("unexpected '{}'", token)),
+            None => Err(String::from("unexpected end of expression")),
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Expr::Add(_, _) => 1,
+            Expr::Mult(_, _) => 2,
+            Expr::Old | Expr::Num(_) => 3,
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Operands are bracketed only when they bind more loosely than the operator. Both
+        // operators are associative, so a right hand side of equal precedence needs none
+        let operand = |f: &mut fmt::Formatter, e: &Expr| match e.precedence() < self.precedence() {
+            true => write!(f, "({})", e),
+            false => write!(f, "{}", e),
+        };
+        match self {
+            Expr::Old => write!(f, "old"),
+            Expr::Num(n) => write!(f, "{}", n),
+            Expr::Add(a, b) | Expr::Mult(a, b) => {
+                operand(f, a)?;
+                write!(f, " {} ", if matches!(self, Expr::Add(_, _)) { '+' } else { '*' })?;
+                operand(f, b)
+            }
+        }
+    }
+}
+
+fn gcd(a: i128, b: i128) -> i128 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+// Worry levels can be kept modulo the LCM of all the divisors without changing any monkey's test
+fn worry_modulus(monkeys: &[Monkey]) -> i128 {
+    monkeys.iter().fold(1, |lcm, monkey| lcm / gcd(lcm, monkey.test) * monkey.test)
 }
 
-fn add(x: i128, y: i128, m: i128) -> i128 {
-    ((x % m) + (y % m)) % m
+// The modulus to reduce worry levels by, if any. Dividing by 3 after reducing gives a different
+// result to dividing the full value, so with relief the worry levels are kept as they are
+fn reduction(monkeys: &[Monkey], divide_by_3: bool) -> Option<i128> {
+    match divide_by_3 {
+        true => None,
+        false => Some(worry_modulus(monkeys)),
+    }
+}
+
+// Sum of two values already reduced below `m`, which always fits in a u128
+fn add_mod(a: i128, b: i128, m: i128) -> i128 {
+    match a.checked_add(b) {
+        Some(sum) => sum % m,
+        None => ((a as u128 + b as u128) % m as u128) as i128,
+    }
 }
 
-fn play_rounds(monkeys: &mut Vec<Monkey>, rounds: usize, divide_by_3: bool) {
+// Product of two values already reduced below `m`, by doubling and adding when the moduli
+// are too large to multiply directly
+fn mul_mod(a: i128, b: i128, m: i128) -> i128 {
+    if let Some(product) = a.checked_mul(b) {
+        return product % m;
+    }
+    let (mut a, mut b, m) = (a as u128, b as u128, m as u128);
+    let mut product = 0;
+    while b > 0 {
+        if b & 1 == 1 {
+            product = (product + a) % m;
+        }
+        a = (a + a) % m;
+        b >>= 1;
+    }
+    product as i128
+}
+
+// Line numbers start at 1
+#[derive(Debug, PartialEq)]
+struct ParseError {
+    line: usize,
+    message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+// Reads the line that should start with `prefix`, returning the rest of it
+fn field<'a>(lines: &mut impl Iterator<Item = (usize, &'a str)>, prefix: &str, last_line: usize) -> Result<(usize, &'a str), ParseError> {
+    match lines.next() {
+        Some((line, text)) => match text.trim().strip_prefix(prefix) {
+            Some(rest) => Ok((line, rest.trim())),
+            None => Err(ParseError { line, message: format!("expected '{}'", prefix) }),
+        },
+        None => Err(ParseError { line: last_line, message: format!("missing '{}'", prefix) }),
+    }
+}
+
+fn number<T: std::str::FromStr>(text: &str, line: usize) -> Result<T, ParseError> {
+    text.parse().map_err(|_| ParseError { line, message: format!("'{}' is not a valid number", text) })
+}
+
+fn parse_monkey<'a>(lines: &mut impl Iterator<Item = (usize, &'a str)>, idx: usize, last_line: usize) -> Result<Monkey, ParseError> {
+    let (line, name) = field(lines, "Monkey", last_line)?;
+    if name != format!("{}:", idx) {
+        return Err(ParseError { line, message: format!("expected 'Monkey {}:'", idx) });
+    }
+
+    let (line, items) = field(lines, "Starting items:", last_line)?;
+    let items = items.split(',')
+        .map(|item| item.trim())
+        .filter(|item| !item.is_empty())
+        .map(|item| number(item, line))
+        .collect::<Result<Vec<i128>, ParseError>>()?;
+
+    let (line, operation) = field(lines, "Operation: new =", last_line)?;
+    let op = Expr::parse(operation)
+        .map_err(|message| ParseError { line, message: format!("invalid operation - {}", message) })?;
+
+    let (line, test) = field(lines, "Test: divisible by", last_line)?;
+    let test = number(test, line)?;
+    if test <= 0 {
+        return Err(ParseError { line, message: String::from("divisor must be positive") });
+    }
+    let (line, passed) = field(lines, "If true: throw to monkey", last_line)?;
+    let test_passed = number(passed, line)?;
+    let (line, failed) = field(lines, "If false: throw to monkey", last_line)?;
+    let test_failed = number(failed, line)?;
+
+    Ok(Monkey::new(items, op, test, test_passed, test_failed))
+}
+
+// Parses notes in the puzzle's format
+fn parse_monkeys(source: &str) -> Result<Vec<Monkey>, ParseError> {
+    let last_line = source.lines().count();
+    let mut lines = source.lines()
+        .enumerate()
+        .map(|(idx, text)| (idx + 1, text))
+        .filter(|(_, text)| !text.trim().is_empty())
+        .peekable();
+
+    let mut monkeys = Vec::new();
+    while lines.peek().is_some() {
+        monkeys.push(parse_monkey(&mut lines, monkeys.len(), last_line)?);
+    }
+
+    for (idx, monkey) in monkeys.iter().enumerate() {
+        for target in [monkey.test_passed, monkey.test_failed] {
+            if target >= monkeys.len() || target == idx {
+                return Err(ParseError { line: last_line, message: format!("monkey {} cannot throw to monkey {}", idx, target) });
+            }
+        }
+    }
+
+    Ok(monkeys)
+}
+
+fn play_rounds(monkeys: &mut [Monkey], rounds: usize, divide_by_3: bool) {
+    let modulus = reduction(monkeys, divide_by_3);
     for _ in 0..rounds {
         for m_idx in 0..monkeys.len() {
-            
+
             // Transfer ownership of items out of monkeys and replace with a cleared version
-            let items = monkeys.get_mut(m_idx).unwrap().items.to_owned();
-            monkeys.get_mut(m_idx).unwrap().items = Vec::new();
+            let items = std::mem::take(&mut monkeys[m_idx].items);
 
             // Get an address to the monkey (but we won't be modifying it)
-            let current = monkeys.get(m_idx).unwrap();
+            let current = &monkeys[m_idx];
             let pass_idx = current.test_passed;
             let fail_idx = current.test_failed;
-            let op  = current.op;
             let test = current.test;
 
-            items.iter().for_each(|item| {
-                let mut worry = (op)(*item);
-                if divide_by_3 {
-                    worry = worry / 3;
-                }
+            let thrown = items.iter()
+                .map(|item| {
+                    let mut worry = current.op.eval(*item, modulus);
+                    if divide_by_3 {
+                        worry /= 3;
+                    }
+                    worry
+                })
+                .collect::<Vec<i128>>();
+
+            thrown.into_iter().for_each(|worry| {
                 if worry % test == 0 {
-                    monkeys.get_mut(pass_idx).unwrap().items.push(worry);
+                    monkeys[pass_idx].items.push(worry);
                 } else {
-                    monkeys.get_mut(fail_idx).unwrap().items.push(worry);
+                    monkeys[fail_idx].items.push(worry);
                 }
             });
-            monkeys.get_mut(m_idx).unwrap().count += items.len() as i128;
+            monkeys[m_idx].count += items.len() as i128;
         }
     }
 }
 
-fn calc_score(monkeys: &Vec<Monkey>) -> i128 {
-    let mut counts:Vec<i128> = monkeys.iter()
-        .map(|m| m.count)
-        .collect();
+fn calc_score(monkeys: &[Monkey]) -> i128 {
+    score(&monkeys.iter().map(|m| m.count).collect::<Vec<i128>>())
+}
 
+// Monkey business, the product of the two highest inspection counts
+fn score(counts: &[i128]) -> i128 {
+    let mut counts = counts.to_vec();
     counts.sort();
 
     counts.iter()
@@ -70,41 +309,383 @@ fn calc_score(monkeys: &Vec<Monkey>) -> i128 {
         .product()
 }
 
+// The state of the game at the end of a round, round 0 being the notes as written
+#[derive(Debug, PartialEq)]
+struct Snapshot {
+    round: usize,
+    items: Vec<Vec<i128>>,
+    inspections: Vec<i128>,
+}
+
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "== After round {} ==", self.round)?;
+        for (idx, (items, count)) in self.items.iter().zip(&self.inspections).enumerate() {
+            let items = items.iter().map(|item| item.to_string()).collect::<Vec<String>>();
+            writeln!(f, "Monkey {} ({} inspections): {}", idx, count, items.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+fn snapshot(monkeys: &[Monkey], round: usize) -> Snapshot {
+    Snapshot {
+        round,
+        items: monkeys.iter().map(|m| m.items.clone()).collect(),
+        inspections: monkeys.iter().map(|m| m.count).collect(),
+    }
+}
+
+// Plays the given number of rounds, keeping the state after each one
+fn play_snapshots(monkeys: &mut [Monkey], rounds: usize, divide_by_3: bool) -> Vec<Snapshot> {
+    let mut snapshots = vec![snapshot(monkeys, 0)];
+    for round in 1..=rounds {
+        play_rounds(monkeys, 1, divide_by_3);
+        snapshots.push(snapshot(monkeys, round));
+    }
+    snapshots
+}
+
+// Items never affect each other, so each can be followed on its own. A monkey that throws to a
+// monkey after it in the order sees the item inspected again in the same round, otherwise the
+// item waits for the next round
+fn item_round(monkeys: &[Monkey], mut monkey: usize, mut worry: i128, modulus: Option<i128>, divide_by_3: bool) -> (Vec<usize>, usize, i128) {
+    let mut inspected_by = Vec::new();
+    loop {
+        inspected_by.push(monkey);
+        let current = &monkeys[monkey];
+        worry = current.op.eval(worry, modulus);
+        if divide_by_3 {
+            worry /= 3;
+        }
+        let target = if worry % current.test == 0 { current.test_passed } else { current.test_failed };
+        if target < monkey {
+            return (inspected_by, target, worry);
+        }
+        monkey = target;
+    }
+}
+
+// Where a monkey's item is at the start of a round
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+struct ItemState {
+    monkey: usize,
+    worry: i128,
+}
+
+// The path of one item, as the monkeys that inspect it in each round. The item's state at the
+// start of each round only depends on the one before, so once it repeats the rounds from
+// `cycle_start` repeat forever
+#[derive(Debug, PartialEq)]
+struct Trajectory {
+    rounds: Vec<Vec<usize>>,
+    cycle_start: Option<usize>,
+}
+
+impl Trajectory {
+    fn cycle_len(&self) -> Option<usize> {
+        self.cycle_start.map(|start| self.rounds.len() - start)
+    }
+
+    // Inspections per monkey over the first `rounds` rounds, skipping over whole cycles
+    fn inspections(&self, monkey_count: usize, rounds: usize) -> Vec<i128> {
+        let mut counts = vec![0; monkey_count];
+        let mut add = |range: std::ops::Range<usize>, times: i128| {
+            for monkey in self.rounds[range].iter().flatten() {
+                counts[*monkey] += times;
+            }
+        };
+        match self.cycle_start {
+            Some(start) if rounds > self.rounds.len() => {
+                let len = self.rounds.len() - start;
+                let (cycles, rest) = ((rounds - start) / len, (rounds - start) % len);
+                add(0..start, 1);
+                add(start..self.rounds.len(), cycles as i128);
+                add(start..start + rest, 1);
+            }
+            _ => add(0..rounds.min(self.rounds.len()), 1),
+        }
+        counts
+    }
+}
+
+// Follows one item for up to `rounds` rounds, stopping early once its state repeats
+fn trajectory(monkeys: &[Monkey], start: ItemState, rounds: usize, divide_by_3: bool) -> Trajectory {
+    let modulus = reduction(monkeys, divide_by_3);
+    let mut seen = HashMap::new();
+    let mut state = start;
+    let mut path = Vec::new();
+    while path.len() < rounds {
+        if let Some(round) = seen.insert(state, path.len()) {
+            return Trajectory { rounds: path, cycle_start: Some(round) };
+        }
+        let (inspected_by, monkey, worry) = item_round(monkeys, state.monkey, state.worry, modulus, divide_by_3);
+        path.push(inspected_by);
+        state = ItemState { monkey, worry };
+    }
+    Trajectory { rounds: path, cycle_start: None }
+}
+
+// Inspections per monkey after `rounds` rounds, from each item's trajectory rather than
+// simulating every round
+fn fast_forward(monkeys: &[Monkey], rounds: usize, divide_by_3: bool) -> Vec<i128> {
+    let mut counts = monkeys.iter().map(|m| m.count).collect::<Vec<i128>>();
+    for (idx, monkey) in monkeys.iter().enumerate() {
+        for item in monkey.items.iter() {
+            let start = ItemState { monkey: idx, worry: *item };
+            let path = trajectory(monkeys, start, rounds, divide_by_3);
+            for (count, extra) in counts.iter_mut().zip(path.inspections(monkeys.len(), rounds)) {
+                *count += extra;
+            }
+        }
+    }
+    counts
+}
+
+// Inspections per monkey after `rounds` rounds, playing every item's rounds on its own and in
+// parallel instead of passing all the items between the monkeys together
+fn play_items_parallel(monkeys: &[Monkey], rounds: usize, divide_by_3: bool) -> Vec<i128> {
+    let modulus = reduction(monkeys, divide_by_3);
+    let items = monkeys.iter()
+        .enumerate()
+        .flat_map(|(idx, monkey)| monkey.items.iter().map(move |item| ItemState { monkey: idx, worry: *item }))
+        .collect::<Vec<ItemState>>();
+
+    let played = items.par_iter()
+        .map(|start| {
+            let mut counts = vec![0; monkeys.len()];
+            let mut state = *start;
+            for _ in 0..rounds {
+                let (inspected_by, monkey, worry) = item_round(monkeys, state.monkey, state.worry, modulus, divide_by_3);
+                inspected_by.iter().for_each(|m| counts[*m] += 1);
+                state = ItemState { monkey, worry };
+            }
+            counts
+        })
+        .reduce(|| vec![0; monkeys.len()], |a, b| a.iter().zip(b).map(|(x, y)| x + y).collect::<Vec<i128>>());
+
+    monkeys.iter()
+        .zip(played)
+        .map(|(monkey, count)| monkey.count + count)
+        .collect()
+}
+
+// Writes monkeys back out in the puzzle's format, optionally with simplified operations
+fn to_notes(monkeys: &[Monkey], simplify: bool) -> String {
+    monkeys.iter()
+        .enumerate()
+        .map(|(idx, monkey)| {
+            let items = monkey.items.iter().map(|item| item.to_string()).collect::<Vec<String>>();
+            let op = if simplify { monkey.op.simplify() } else { monkey.op.clone() };
+            format!("Monkey {}:\n  Starting items: {}\n  Operation: new = {}\n  Test: divisible by {}\n    If true: throw to monkey {}\n    If false: throw to monkey {}\n",
+                idx, items.join(", "), op, monkey.test, monkey.test_passed, monkey.test_failed)
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+fn read_monkeys(path: &str) -> Vec<Monkey> {
+    let input_data = fs::read_to_string(path).unwrap();
+    parse_monkeys(&input_data).unwrap_or_else(|e| {
+        eprintln!("Invalid notes in {} - {}", path, e);
+        std::process::exit(1);
+    })
+}
+
+// With no arguments this solves the puzzle, otherwise:
+//   notes [simplify]            prints the parsed notes, with constant operations folded if asked
+//   modulus                     the LCM of the divisors that worry levels are reduced by
+//   rounds <n> [relief]         the items and inspections after each of the first n rounds
+//   path <monkey> <item> <n>    the monkeys that inspect an item in each round, and its cycle
+//   fast <n> [relief]           the score after n rounds, fast-forwarding through item cycles
+//   parallel <n> [relief]       the score after n rounds, playing each item's rounds in parallel
+// where `relief` divides worry levels by 3 after each inspection, as in part 1
 fn main() {
-    // let example = vec![
-    //     Monkey::new(vec![79,98], |x| mult(x,19,96577), 23, 2, 3),
-    //     Monkey::new(vec![54,65,75,74], |x| add(x,6,96577), 19, 2, 0),
-    //     Monkey::new(vec![79,60,97], |x| sq(x,96577), 13, 1, 3),
-    //     Monkey::new(vec![74], |x| add(x,3,96577), 17, 0, 1),
-    // ];
-
-    let input1 = vec![
-        Monkey::new(vec![65,78],|x| mult(x,3,9699690), 5, 2, 3),
-        Monkey::new(vec![54,78,86,79,73,64,85,88],|x| add(x,8,9699690), 11, 4, 7),
-        Monkey::new(vec![69,97,77,88,87], |x| add(x,2,9699690), 2, 5, 3),
-        Monkey::new(vec![99], |x| add(x,4,9699690), 13, 1, 5),
-        Monkey::new(vec![60, 57, 52], |x| mult(x,19,9699690), 7, 7, 6),
-        Monkey::new(vec![91, 82, 85, 73, 84, 53], |x| add(x,5,9699690), 3, 4, 1),
-        Monkey::new(vec![88, 74, 68, 56], |x| sq(x, 9699690), 17, 0, 2),
-        Monkey::new(vec![54, 82, 72, 71, 53, 99, 67], |x| add(x,1,9699690), 19, 6, 0),
-    ];
-
-    let mut monkeys1 = input1;
+    let mut monkeys = read_monkeys("src/day11/input.txt");
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    let rounds = |n: &str| n.parse::<usize>().expect("Invalid round count");
+    match args.iter().map(String::as_str).collect::<Vec<&str>>().as_slice() {
+        ["notes"] => print!("{}", to_notes(&monkeys, false)),
+        ["notes", "simplify"] => print!("{}", to_notes(&monkeys, true)),
+        ["modulus"] => println!("{}", worry_modulus(&monkeys)),
+        ["rounds", n, relief @ ..] if relief.len() <= 1 => {
+            for snapshot in play_snapshots(&mut monkeys, rounds(n), relief == ["relief"]) {
+                println!("{}", snapshot);
+            }
+        }
+        ["path", monkey, item, n] => {
+            let monkey = monkey.parse::<usize>().expect("Invalid monkey");
+            let worry = monkeys[monkey].items[item.parse::<usize>().expect("Invalid item")];
+            let path = trajectory(&monkeys, ItemState { monkey, worry }, rounds(n), false);
+            for (round, inspected_by) in path.rounds.iter().enumerate() {
+                println!("Round {}: {:?}", round + 1, inspected_by);
+            }
+            match (path.cycle_start, path.cycle_len()) {
+                (Some(start), Some(len)) => println!("Repeats every {} rounds from round {}", len, start + 1),
+                _ => println!("No cycle within {} rounds", n),
+            }
+        }
+        ["fast", n, relief @ ..] if relief.len() <= 1 =>
+            println!("Score - {}", score(&fast_forward(&monkeys, rounds(n), relief == ["relief"]))),
+        ["parallel", n, relief @ ..] if relief.len() <= 1 =>
+            println!("Score - {}", score(&play_items_parallel(&monkeys, rounds(n), relief == ["relief"]))),
+        [] => solve(),
+        _ => eprintln!("Usage: day11 [notes [simplify] | modulus | rounds <n> [relief] | path <monkey> <item> <n> | fast <n> [relief] | parallel <n> [relief]]"),
+    }
+}
+
+fn solve() {
+    let mut monkeys1 = read_monkeys("src/day11/input.txt");
     play_rounds(&mut monkeys1, 20, true);
     println!("Part 1 : Score - {}", calc_score(&monkeys1));
 
-    let input2 = vec![
-        Monkey::new(vec![65,78],|x| mult(x,3,9699690), 5, 2, 3),
-        Monkey::new(vec![54,78,86,79,73,64,85,88],|x| add(x,8,9699690), 11, 4, 7),
-        Monkey::new(vec![69,97,77,88,87], |x| add(x,2,9699690), 2, 5, 3),
-        Monkey::new(vec![99], |x| add(x,4,9699690), 13, 1, 5),
-        Monkey::new(vec![60, 57, 52], |x| mult(x,19,9699690), 7, 7, 6),
-        Monkey::new(vec![91, 82, 85, 73, 84, 53], |x| add(x,5,9699690), 3, 4, 1),
-        Monkey::new(vec![88, 74, 68, 56], |x| sq(x, 9699690), 17, 0, 2),
-        Monkey::new(vec![54, 82, 72, 71, 53, 99, 67], |x| add(x,1,9699690), 19, 6, 0),
-    ];
-
-    let mut monkeys2 = input2;
+    let mut monkeys2 = read_monkeys("src/day11/input.txt");
     play_rounds(&mut monkeys2, 10000, false);
     println!("Part 2 : Score - {}", calc_score(&monkeys2));
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn day11_example() {
+        let mut monkeys = read_monkeys("src/day11/example.txt");
+        assert_eq!(monkeys.len(), 4);
+        assert_eq!(monkeys[1].items, [54, 65, 75, 74]);
+        assert_eq!(monkeys[2].op, Expr::Mult(Box::new(Expr::Old), Box::new(Expr::Old)));
+        assert_eq!(worry_modulus(&monkeys), 23 * 19 * 13 * 17);
+        assert_eq!((monkeys[2].test, monkeys[2].test_passed, monkeys[2].test_failed), (13, 1, 3));
+
+        play_rounds(&mut monkeys, 20, true);
+        assert_eq!(monkeys.iter().map(|m| m.count).collect::<Vec<i128>>(), [101, 95, 7, 105]);
+        assert_eq!(calc_score(&monkeys), 10605);
+
+        let mut monkeys = read_monkeys("src/day11/example.txt");
+        play_rounds(&mut monkeys, 10000, false);
+        assert_eq!(calc_score(&monkeys), 2713310158);
+    }
+
+    #[test]
+    fn day11_parse_errors() {
+        let input_data = fs::read_to_string("src/day11/example.txt").unwrap();
+        let error = |source: &str| parse_monkeys(source).err().unwrap();
+
+        assert_eq!(error(&input_data.replace("old * 19", "old - 19")),
+            ParseError { line: 3, message: "invalid operation - unexpected '-'".to_string() });
+        assert_eq!(error(&input_data.replace("Monkey 1:", "Monkey 7:")),
+            ParseError { line: 8, message: "expected 'Monkey 1:'".to_string() });
+        assert_eq!(error(&input_data.replace("79, 98", "79, x")),
+            ParseError { line: 2, message: "'x' is not a valid number".to_string() });
+        assert_eq!(error(&input_data.replace("throw to monkey 3\n\nMonkey 1", "throw to monkey 4\n\nMonkey 1")).message,
+            "monkey 0 cannot throw to monkey 4");
+        assert_eq!(error("Monkey 0:\n  Starting items: 1\n").message, "missing 'Operation: new ='");
+    }
+
+    #[test]
+    fn day11_snapshots() {
+        let mut monkeys = read_monkeys("src/day11/example.txt");
+        let snapshots = play_snapshots(&mut monkeys, 20, true);
+        assert_eq!(snapshots.len(), 21);
+        assert_eq!(snapshots[1].items, [vec![20, 23, 27, 26], vec![2080, 25, 167, 207, 401, 1046], vec![], vec![]]);
+        assert_eq!(snapshots[1].inspections, [2, 4, 3, 5]);
+        assert_eq!(snapshots[20].inspections, [101, 95, 7, 105]);
+        assert_eq!(snapshots[1].to_string().lines().nth(1), Some("Monkey 0 (2 inspections): 20, 23, 27, 26"));
+
+        // Following every item on its own gives the same counts as playing all the rounds
+        let monkeys = read_monkeys("src/day11/example.txt");
+        for rounds in [1, 20, 1000] {
+            let mut played = read_monkeys("src/day11/example.txt");
+            play_rounds(&mut played, rounds, false);
+            assert_eq!(fast_forward(&monkeys, rounds, false), played.iter().map(|m| m.count).collect::<Vec<i128>>());
+        }
+        assert_eq!(score(&fast_forward(&monkeys, 20, true)), 10605);
+        assert_eq!(score(&fast_forward(&monkeys, 10000, false)), 2713310158);
+
+        // 79 starts with monkey 0, which throws it on to monkey 3 in the same round
+        let start = ItemState { monkey: 0, worry: 79 };
+        let path = trajectory(&monkeys, start, 3, false);
+        assert_eq!(path.rounds, [vec![0, 3], vec![1], vec![0, 3]]);
+        assert_eq!(path.cycle_start, None);
+
+        // Skipping cycles agrees with following the item round by round
+        let path = trajectory(&monkeys, start, usize::MAX, false);
+        let rounds = path.rounds.len() + 3 * path.cycle_len().unwrap() + 2;
+        let mut counts = vec![0; 4];
+        let mut state = start;
+        for _ in 0..rounds {
+            let (inspected_by, monkey, worry) = item_round(&monkeys, state.monkey, state.worry, reduction(&monkeys, false), false);
+            inspected_by.iter().for_each(|m| counts[*m] += 1);
+            state = ItemState { monkey, worry };
+        }
+        assert_eq!(path.inspections(4, rounds), counts);
+    }
+
+    #[test]
+    fn day11_relief_unreduced() {
+        // 10 * 5 / 3 is 16, but reducing by the LCM of 6 first would give 50 % 6 / 3 = 0
+        let notes = "Monkey 0:\n  Starting items: 10\n  Operation: new = old * 5\n  Test: divisible by 2\n    If true: throw to monkey 1\n    If false: throw to monkey 2\n\n\
+            Monkey 1:\n  Starting items:\n  Operation: new = old + 0\n  Test: divisible by 3\n    If true: throw to monkey 0\n    If false: throw to monkey 0\n\n\
+            Monkey 2:\n  Starting items:\n  Operation: new = old\n  Test: divisible by 3\n    If true: throw to monkey 0\n    If false: throw to monkey 0\n";
+        let mut monkeys = parse_monkeys(notes).unwrap();
+        assert_eq!(worry_modulus(&monkeys), 6);
+        let expected = fast_forward(&monkeys, 1, true);
+        let snapshots = play_snapshots(&mut monkeys, 1, true);
+        assert_eq!(snapshots[1].items, [vec![5], vec![], vec![]]);
+        assert_eq!(snapshots[1].inspections, [1, 1, 0]);
+        assert_eq!(expected, [1, 1, 0]);
+
+        let monkeys = parse_monkeys(notes).unwrap();
+        let mut played = parse_monkeys(notes).unwrap();
+        play_rounds(&mut played, 50, true);
+        let counts = played.iter().map(|m| m.count).collect::<Vec<i128>>();
+        assert_eq!(fast_forward(&monkeys, 50, true), counts);
+        assert_eq!(play_items_parallel(&monkeys, 50, true), counts);
+    }
+
+    #[test]
+    fn day11_parallel() {
+        for (path, rounds, relief) in [("src/day11/example.txt", 10000, false), ("src/day11/input.txt", 10000, false), ("src/day11/input.txt", 20, true)] {
+            let mut played = read_monkeys(path);
+            let parallel = play_items_parallel(&played, rounds, relief);
+            play_rounds(&mut played, rounds, relief);
+            assert_eq!(parallel, played.iter().map(|m| m.count).collect::<Vec<i128>>());
+        }
+
+        // Carrying on from a game already in progress
+        let mut played = read_monkeys("src/day11/input.txt");
+        play_rounds(&mut played, 5, false);
+        let parallel = play_items_parallel(&played, 100, false);
+        assert_eq!(parallel, fast_forward(&played, 100, false));
+        play_rounds(&mut played, 100, false);
+        assert_eq!(parallel, played.iter().map(|m| m.count).collect::<Vec<i128>>());
+    }
+
+    #[test]
+    fn day11_expressions() {
+        let expr = Expr::parse("(old + 2) * 3 * old + 1 * 4").unwrap();
+        assert_eq!(expr.to_string(), "(old + 2) * 3 * old + 1 * 4");
+        assert_eq!(expr.eval(5, None), 109);
+        assert_eq!(expr.eval(5, Some(100)), 9);
+        assert_eq!(expr.simplify().to_string(), "(old + 2) * 3 * old + 4");
+        assert_eq!(Expr::parse("old * (1 + 0 * old) + 2 * 3").unwrap().simplify().to_string(), "old + 6");
+        assert_eq!(Expr::parse("old * (old * 2)").unwrap().to_string(), "old * old * 2");
+
+        // Large moduli and constants don't overflow
+        assert_eq!(Expr::parse("old * old").unwrap().eval(1 << 64, Some(i128::MAX)), 2);
+        assert_eq!(Expr::parse("old + old").unwrap().eval(i128::MAX - 1, Some(i128::MAX)), i128::MAX - 2);
+        let big = "old + 18446744073709551615 * 18446744073709551615";
+        assert_eq!(Expr::parse(big).unwrap().simplify().to_string(), big);
+        assert_eq!(Expr::parse("old + 3 * 18446744073709551615").unwrap().simplify().to_string(), "old + 55340232221128654845");
+        assert_eq!(Expr::parse("(old + 1").err().unwrap(), "missing ')'");
+        assert_eq!(Expr::parse("old +").err().unwrap(), "unexpected end of expression");
+
+        let input_data = fs::read_to_string("src/day11/example.txt").unwrap();
+        let monkeys = parse_monkeys(&input_data).unwrap();
+        assert_eq!(to_notes(&monkeys, false), input_data);
+
+        // Divisors sharing factors only need their LCM
+        let monkeys = parse_monkeys(&input_data.replace("by 23", "by 26")).unwrap();
+        assert_eq!(worry_modulus(&monkeys), 26 * 19 * 17);
+    }
+}
@@ -1,4 +1,4 @@
-use std::{fs, collections::HashMap, fmt};
+use std::{env, fs, cmp::Reverse, collections::{hash_map::Entry, BinaryHeap, HashMap, VecDeque}, fmt};
 use pathfinding::prelude::dijkstra;
 
 #[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
@@ -9,7 +9,9 @@ struct Map {
     start: Pos,
     end: Pos,
     lowest: Vec<Pos>,
-    heights: HashMap<Pos, usize>
+    heights: HashMap<Pos, usize>,
+    width: usize,
+    height: usize,
 }
 
 impl Map {
@@ -20,13 +22,12 @@ impl Map {
 
         let heights = source.lines()
             .enumerate()
-            .map(|(y, line)| {
+            .flat_map(|(y, line)| {
                 line.chars()
                     .enumerate()
                     .map(|(x,c)| (x,y,c))
                     .collect::<Vec<(usize,usize,char)>>()
                 })
-            .flatten()
             .map(|(x,y,c)| (Pos(x,y), c))
             .inspect(|(p, c)| {
                 if *c == 'S' {
@@ -40,7 +41,9 @@ impl Map {
             .map(|(p,c)| (p, Self::get_height(c)))
             .collect::<HashMap<Pos, usize>>();
 
-        Map { start:start, end: end, lowest: lowest, heights: heights }
+        let width = source.lines().map(|line| line.len()).max().unwrap_or(0);
+        let height = source.lines().count();
+        Map { start, end, lowest, heights, width, height }
     }
 
     fn get_height(c_to_find: char) -> usize {
@@ -64,61 +67,298 @@ impl fmt::Display for Pos {
     }
 }
 
+// How a hiker may move between cells. The climb and descent limits are in height levels, with
+// `None` for no limit. `step_cost` is given the height difference of a step (positive when
+// climbing), with `None` for every step costing 1
+#[derive(Clone, Copy, Debug)]
+struct Rules {
+    max_climb: Option<usize>,
+    max_descent: Option<usize>,
+    diagonals: bool,
+    step_cost: Option<fn(isize) -> usize>,
+}
+
+impl Rules {
+    // Climb at most one level, descend any amount, one step at a time
+    fn puzzle() -> Rules {
+        Rules { max_climb: Some(1), max_descent: None, diagonals: false, step_cost: None }
+    }
+
+    // The cost of stepping between cells of the given heights, if allowed
+    fn step(&self, from: usize, to: usize) -> Option<usize> {
+        let allowed = match to >= from {
+            true => self.max_climb.is_none_or(|max| to - from <= max),
+            false => self.max_descent.is_none_or(|max| from - to <= max),
+        };
+        allowed.then(|| self.step_cost.map_or(1, |cost| cost(to as isize - from as isize)))
+    }
+}
+
 impl Pos {
-    fn neighbours(&self, map: &Map) -> Vec<(Pos, usize)> {
-      let &Pos(x, y) = self;
+    // The cells next to this one on the map, diagonals included if the rules allow them
+    fn adjacent(&self, map: &Map, rules: &Rules) -> Vec<Pos> {
+        let &Pos(x, y) = self;
+        let mut offsets = vec![(0, -1), (0, 1), (-1, 0), (1, 0)];
+        if rules.diagonals {
+            offsets.extend([(-1, -1), (1, -1), (-1, 1), (1, 1)]);
+        }
+        offsets.into_iter()
+            .filter_map(|(dx, dy)| Some(Pos(x.checked_add_signed(dx)?, y.checked_add_signed(dy)?)))
+            .filter(|p| map.heights.contains_key(p))
+            .collect()
+    }
 
-      let current_height = *map.heights.get(self).unwrap_or(&0);
-      let mut positions = Vec::<Pos>::new();
+    // Cells off the map have no neighbours or predecessors
+    fn neighbours(&self, map: &Map, rules: &Rules) -> Vec<(Pos, usize)> {
+        let Some(&current_height) = map.heights.get(self) else {
+            return Vec::new();
+        };
+        self.adjacent(map, rules)
+            .into_iter()
+            .filter_map(|p| Some((p, rules.step(current_height, map.heights[&p])?)))
+            .collect()
+    }
 
-      if y != 0 && (*map.heights.get(&Pos(x, y-1)).unwrap_or(&99999) <= current_height + 1 ){
-        positions.push(Pos(x, y-1))
-      }
+    // The cells that can step onto this one and what it costs them, ie. the neighbours in reverse
+    fn predecessors(&self, map: &Map, rules: &Rules) -> Vec<(Pos, usize)> {
+        let Some(&current_height) = map.heights.get(self) else {
+            return Vec::new();
+        };
+        self.adjacent(map, rules)
+            .into_iter()
+            .filter_map(|p| Some((p, rules.step(map.heights[&p], current_height)?)))
+            .collect()
+    }
+}
 
-      if *map.heights.get(&Pos(x, y+1)).unwrap_or(&99999) <= current_height + 1 {
-        positions.push(Pos(x, y+1))
-      }
+// Searches backwards from the end, giving the cost of the cheapest path to the end from every
+// cell that can reach it. When every step costs 1 this is a breadth first search, otherwise
+// cells are visited cheapest first
+fn distances_to_end(map: &Map, rules: &Rules) -> HashMap<Pos, usize> {
+    match rules.step_cost {
+        None => steps_to_end(map, rules),
+        Some(_) => costs_to_end(map, rules),
+    }
+}
 
-      if x != 0 && *map.heights.get(&Pos(x-1, y)).unwrap_or(&99999) <= current_height + 1 {
-        positions.push(Pos(x-1, y))
-      }
+fn steps_to_end(map: &Map, rules: &Rules) -> HashMap<Pos, usize> {
+    let mut distances = HashMap::from([(map.end, 0)]);
+    let mut queue = VecDeque::from([map.end]);
+    while let Some(p) = queue.pop_front() {
+        let distance = distances[&p] + 1;
+        for (prev, _) in p.predecessors(map, rules) {
+            if let Entry::Vacant(entry) = distances.entry(prev) {
+                entry.insert(distance);
+                queue.push_back(prev);
+            }
+        }
+    }
+    distances
+}
 
-      if *map.heights.get(&Pos(x+1, y)).unwrap_or(&99999) <= current_height + 1 {
-        positions.push(Pos(x+1, y))
-      }
-      //println!("CURR {:?}:{} , POS{:?}",self, current_height, positions);
-      positions.into_iter().map(|p| (p, 1)).collect()
-      
+fn costs_to_end(map: &Map, rules: &Rules) -> HashMap<Pos, usize> {
+    let mut distances = HashMap::from([(map.end, 0)]);
+    let mut queue = BinaryHeap::from([Reverse((0, map.end))]);
+    while let Some(Reverse((distance, p))) = queue.pop() {
+        if distance > distances[&p] {
+            continue;
+        }
+        for (prev, cost) in p.predecessors(map, rules) {
+            let distance = distance + cost;
+            if distances.get(&prev).is_none_or(|d| distance < *d) {
+                distances.insert(prev, distance);
+                queue.push(Reverse((distance, prev)));
+            }
+        }
     }
+    distances
+}
+
+// The shortest path to the end from any of the starts
+fn shortest_from_any(distances: &HashMap<Pos, usize>, starts: &[Pos]) -> Option<usize> {
+    starts.iter()
+        .filter_map(|p| distances.get(p))
+        .copied()
+        .min()
+}
+
+
+// The cells along the cheapest route from start to end, including both, and its cost
+fn calc_path(map: &Map, rules: &Rules, start: Pos) -> Option<(Vec<Pos>, usize)> {
+    dijkstra(&start, |p| p.neighbours(map, rules), |p| *p == map.end)
+}
+
+fn calc_path_cost(map: &Map, rules: &Rules, start: Pos) -> Option<usize> {
+    calc_path(map, rules, start).map(|(_, cost)| cost)
 }
 
+// Draws the route like the puzzle does, each step as an arrow towards the next cell, the end
+// as `E` and everything else as `.`
+fn render_route(map: &Map, route: &[Pos]) -> String {
+    let mut cells = HashMap::from([(map.end, 'E')]);
+    for step in route.windows(2) {
+        let (Pos(x1, y1), Pos(x2, y2)) = (step[0], step[1]);
+        let arrow = match (x2 as isize - x1 as isize, y2 as isize - y1 as isize) {
+            (1, 0) => '>',
+            (-1, 0) => '<',
+            (0, 1) => 'v',
+            (0, -1) => '^',
+            (1, 1) | (-1, -1) => '\\',
+            _ => '/',
+        };
+        cells.insert(step[0], arrow);
+    }
+    (0..map.height)
+        .map(|y| (0..map.width).map(|x| *cells.get(&Pos(x, y)).unwrap_or(&'.')).collect::<String>())
+        .collect::<Vec<String>>()
+        .join("\n")
+}
 
-fn calc_path_cost(map: &Map, start: Pos) -> Option<usize> {
-    let result = dijkstra(&start, |p| p.neighbours(&map), |p| *p == map.end);
-    match result {
-        None => None,
-        Some((_, cost)) => Some(cost),
+// One row per cell for plotting, the distance being blank where the end can't be reached
+fn to_csv(map: &Map, distances: &HashMap<Pos, usize>) -> String {
+    let mut csv = String::from("x,y,height,distance\n");
+    for y in 0..map.height {
+        for x in 0..map.width {
+            if let Some(height) = map.heights.get(&Pos(x, y)) {
+                let distance = distances.get(&Pos(x, y)).map_or(String::new(), |d| d.to_string());
+                csv += &format!("{},{},{},{}\n", x, y, height, distance);
+            }
+        }
     }
+    csv
 }
 
+// A few different hikers to compare on the same map
+fn hikers() -> Vec<(&'static str, Rules)> {
+    vec![
+        ("puzzle", Rules::puzzle()),
+        ("diagonal", Rules { diagonals: true, ..Rules::puzzle() }),
+        ("climber", Rules { max_climb: Some(2), ..Rules::puzzle() }),
+        ("careful", Rules { max_descent: Some(1), ..Rules::puzzle() }),
+        ("tired", Rules { step_cost: Some(|diff| 1 + diff.max(0) as usize * 2), ..Rules::puzzle() }),
+    ]
+}
+
+// With no arguments this solves the puzzle, otherwise:
+//   route [hiker]    draws the cheapest route from the start
+//   csv [hiker]      heights and costs to the end of every cell
+//   hikers           the cheapest route from the start for each hiker
+// where the hiker is one of those in `hikers`, by default the puzzle's
 fn main() {
     let input_data = fs::read_to_string("src/day12/input.txt").unwrap();
 
     let map = Map::new(&input_data);
 
-    println!("Part 1 : Cost - {}", calc_path_cost(&map, map.start).unwrap());
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    let hiker = |name: &[&str]| match name {
+        [] => Some(Rules::puzzle()),
+        [name] => hikers().into_iter().find(|(n, _)| n == name).map(|(_, rules)| rules),
+        _ => None,
+    };
+    match args.iter().map(String::as_str).collect::<Vec<&str>>().as_slice() {
+        ["route", name @ ..] if hiker(name).is_some() => match calc_path(&map, &hiker(name).unwrap(), map.start) {
+            Some((route, _)) => println!("{}", render_route(&map, &route)),
+            None => println!("No route"),
+        },
+        ["csv", name @ ..] if hiker(name).is_some() =>
+            print!("{}", to_csv(&map, &distances_to_end(&map, &hiker(name).unwrap()))),
+        ["hikers"] => {
+            for (name, rules) in hikers() {
+                match calc_path_cost(&map, &rules, map.start) {
+                    Some(cost) => println!("{:<10} {}", name, cost),
+                    None => println!("{:<10} no route", name),
+                }
+            }
+        }
+        [] => solve(&map),
+        _ => eprintln!("Usage: day12 [route [hiker] | csv [hiker] | hikers]"),
+    }
+}
+
+fn solve(map: &Map) {
+    let rules = Rules::puzzle();
+    println!("Part 1 : Cost - {}", calc_path_cost(map, &rules, map.start).unwrap());
 
     let mut start_positions = map.lowest.clone();
     start_positions.push(map.start);
 
-    let lowest_cost = start_positions.iter()
-        .map(|x| calc_path_cost(&map, *x))
-        .filter(|x| x.is_some())
-        .map(|x| x.unwrap())
-        .min()
-        .unwrap();
+    let distances = distances_to_end(map, &rules);
+    let lowest_cost = shortest_from_any(&distances, &start_positions).unwrap();
     println!("Part 2 : Lowest Cost - {}", lowest_cost);
-    
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn day12_example() {
+        let input_data = fs::read_to_string("src/day12/example.txt").unwrap();
+        let map = Map::new(&input_data);
+        let rules = Rules::puzzle();
+        assert_eq!(calc_path_cost(&map, &rules, map.start), Some(31));
 
+        let distances = distances_to_end(&map, &rules);
+        assert_eq!(distances[&map.start], 31);
+        assert_eq!(shortest_from_any(&distances, &map.lowest), Some(29));
+        assert_eq!(distances, costs_to_end(&map, &Rules { step_cost: Some(|_| 1), ..rules }));
 
-}
\ No newline at end of file
+        // Every cell agrees with a search forwards from it
+        for p in map.heights.keys() {
+            assert_eq!(distances.get(p).copied(), calc_path_cost(&map, &rules, *p));
+        }
+    }
+
+    #[test]
+    fn day12_route() {
+        let input_data = fs::read_to_string("src/day12/example.txt").unwrap();
+        let map = Map::new(&input_data);
+        let rules = Rules::puzzle();
+        let (route, cost) = calc_path(&map, &rules, map.start).unwrap();
+        assert_eq!(route.len(), cost + 1);
+        assert_eq!((route[0], route[cost]), (map.start, map.end));
+
+        // Other routes are just as short, so only check the arrows follow the route
+        let rendered = render_route(&map, &route);
+        assert_eq!(rendered.lines().count(), 5);
+        assert_eq!(rendered.chars().filter(|c| "><v^".contains(*c)).count(), 31);
+        assert_eq!(rendered.lines().nth(2).unwrap().find('E'), Some(5));
+        assert!(rendered.starts_with('v') || rendered.starts_with('>'));
+
+        let csv = to_csv(&map, &distances_to_end(&map, &rules));
+        assert_eq!(csv.lines().count(), 41);
+        assert_eq!(csv.lines().nth(1), Some("0,0,0,31"));
+        assert_eq!(csv.lines().nth(22), Some("5,2,25,0"));
+    }
+
+    #[test]
+    fn day12_rules() {
+        let map = Map::new("Sxy\nxyE");
+        assert_eq!(calc_path_cost(&map, &Rules::puzzle(), map.start), None);
+        let distances = distances_to_end(&map, &Rules::puzzle());
+        assert_eq!((distances.get(&map.start), distances.get(&Pos(0, 1))), (None, Some(&2)));
+        assert_eq!(Pos(5, 5).neighbours(&map, &Rules::puzzle()), Vec::new());
+        assert_eq!(Pos(5, 5).predecessors(&map, &Rules::puzzle()), Vec::new());
+        assert_eq!(calc_path_cost(&map, &Rules::puzzle(), Pos(5, 5)), None);
+
+        let climber = Rules { max_climb: Some(24), ..Rules::puzzle() };
+        assert_eq!(calc_path_cost(&map, &climber, map.start), Some(3));
+        let (route, cost) = calc_path(&map, &Rules { diagonals: true, ..climber }, map.start).unwrap();
+        assert_eq!(cost, 2);
+        assert_eq!(render_route(&map, &route).matches(['>', '\\']).count(), 2);
+
+        // The y at the top can only leave by going down a level
+        let map = Map::new("Syx\nxxE");
+        let scrambler = Rules { max_climb: Some(2), ..Rules::puzzle() };
+        assert_eq!(calc_path_cost(&map, &scrambler, Pos(1, 0)), Some(2));
+        assert_eq!(calc_path_cost(&map, &Rules { max_descent: Some(0), ..scrambler }, Pos(1, 0)), None);
+        assert_eq!(calc_path_cost(&map, &Rules { max_descent: Some(1), ..scrambler }, Pos(1, 0)), Some(2));
+
+        let map = Map::new("Sxy\nxyE");
+        let tired = Rules { step_cost: Some(|diff| 1 + diff.max(0) as usize), diagonals: true, ..climber };
+        assert_eq!(calc_path_cost(&map, &tired, map.start), Some(27));
+        for (p, distance) in distances_to_end(&map, &tired) {
+            assert_eq!(Some(distance), calc_path_cost(&map, &tired, p));
+        }
+    }
+}
@@ -1,157 +1,603 @@
-use std::{fs, cmp::Ordering};
+use std::{env, fs, fmt, cmp::Ordering, str::FromStr};
+use serde_json::Value;
 
-#[derive(PartialEq)]
-enum Order {
-    Right,
-    Wrong
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Packet {
+    Int(u64),
+    List(Vec<Packet>),
 }
 
-struct Packet {
-    idx: usize,
-    source: String,
-    indent: usize,
+// Position is the byte offset into the line
+#[derive(Debug, PartialEq)]
+struct ParseError {
+    pos: usize,
+    message: String,
 }
 
-impl Packet {
-    fn new(source: &str) -> Self {
-        Packet { idx: 0, indent:0, source: source.to_string() }
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "at {}: {}", self.pos, self.message)
     }
+}
 
-    fn from_digit(digit: usize) -> String {
-       format!("[{}]", digit).to_string()
+// JSON values that aren't packets
+#[derive(Debug, PartialEq)]
+enum JsonErrorKind {
+    Object,
+    String,
+    Bool,
+    Null,
+    Negative,
+    TooLarge,
+    Float,
+}
+
+// Path is where the value was found, as indexes from the top level like `$[1][0]`
+#[derive(Debug, PartialEq)]
+struct JsonError {
+    path: String,
+    kind: JsonErrorKind,
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let message = match self.kind {
+            JsonErrorKind::Object => "objects are not allowed in packets",
+            JsonErrorKind::String => "strings are not allowed in packets",
+            JsonErrorKind::Bool => "booleans are not allowed in packets",
+            JsonErrorKind::Null => "null is not allowed in packets",
+            JsonErrorKind::Negative => "integers in packets can't be negative",
+            JsonErrorKind::TooLarge => "integer too large",
+            JsonErrorKind::Float => "numbers in packets must be integers",
+        };
+        write!(f, "{}: {}", self.path, message)
     }
+}
 
-    fn get_digit(&self) -> Option<usize> {
-        if self.source.contains("[") {
-            return None
+impl Packet {
+    fn to_json(&self) -> Value {
+        match self {
+            Packet::Int(value) => Value::from(*value),
+            Packet::List(items) => Value::Array(items.iter().map(Packet::to_json).collect()),
         }
-        return Some(self.source.parse::<usize>().unwrap());
     }
-}
 
-impl Iterator for Packet {
-    type Item = Self;
-    fn next(&mut self) -> Option<Self::Item> {
-        self.indent = 0;
-        let part = self.source[1..self.source.len() - 1]
-            .chars()
-            .skip(self.idx)
-            .map(|c| {
-                if c == '[' {
-                    self.indent += 1
-                } else if c == ']' {
-                    self.indent -= 1
+    fn from_json(value: &Value) -> Result<Packet, JsonError> {
+        Packet::from_json_at(value, &mut String::from("$"))
+    }
+
+    fn from_json_at(value: &Value, path: &mut String) -> Result<Packet, JsonError> {
+        let error = |kind| Err(JsonError { path: path.clone(), kind });
+        match value {
+            Value::Array(items) => {
+                let mut packets = Vec::with_capacity(items.len());
+                for (idx, item) in items.iter().enumerate() {
+                    let len = path.len();
+                    path.push_str(&format!("[{}]", idx));
+                    packets.push(Packet::from_json_at(item, path)?);
+                    path.truncate(len);
                 }
-                (self.indent, c)
-            })
-            .take_while(|(indent, c)| (*indent != 0 || *c != ','))
-            .map(|(_, c)| c)
-            .collect::<String>();
+                Ok(Packet::List(packets))
+            }
+            // Integers past u64 are only kept as floats, so whole floats that large were integers
+            Value::Number(n) => match (n.as_u64(), n.is_i64(), n.as_f64()) {
+                (Some(n), _, _) => Ok(Packet::Int(n)),
+                (None, true, _) => error(JsonErrorKind::Negative),
+                (None, false, Some(f)) if f >= u64::MAX as f64 && f.fract() == 0.0 => error(JsonErrorKind::TooLarge),
+                (None, false, _) => error(JsonErrorKind::Float),
+            },
+            Value::Object(_) => error(JsonErrorKind::Object),
+            Value::String(_) => error(JsonErrorKind::String),
+            Value::Bool(_) => error(JsonErrorKind::Bool),
+            Value::Null => error(JsonErrorKind::Null),
+        }
+    }
 
-        if part.is_empty() {
-            return None
+    fn divider(value: u64) -> Packet {
+        Packet::List(vec![Packet::List(vec![Packet::Int(value)])])
+    }
+
+    // Parses the packet starting at `pos`, returning it and the position after it
+    fn parse_at(source: &[u8], pos: usize) -> Result<(Packet, usize), ParseError> {
+        let error = |pos: usize, message: &str| ParseError { pos, message: message.to_string() };
+        match source.get(pos) {
+            Some(b'[') => {
+                let mut items = Vec::new();
+                let mut pos = pos + 1;
+                if source.get(pos) == Some(&b']') {
+                    return Ok((Packet::List(items), pos + 1));
+                }
+                loop {
+                    let (item, next) = Packet::parse_at(source, pos)?;
+                    items.push(item);
+                    match source.get(next) {
+                        Some(b',') => pos = next + 1,
+                        Some(b']') => return Ok((Packet::List(items), next + 1)),
+                        Some(_) => return Err(error(next, "expected ',' or ']'")),
+                        None => return Err(error(next, "unclosed list")),
+                    }
+                }
+            }
+            Some(c) if c.is_ascii_digit() => {
+                let end = source[pos..].iter()
+                    .position(|c| !c.is_ascii_digit())
+                    .map_or(source.len(), |len| pos + len);
+                std::str::from_utf8(&source[pos..end]).unwrap()
+                    .parse()
+                    .map(|value| (Packet::Int(value), end))
+                    .map_err(|_| error(pos, "integer too large"))
+            }
+            Some(_) => Err(error(pos, "expected '[' or an integer")),
+            None => Err(error(pos, "unexpected end of packet")),
         }
-        self.idx += part.len() + 1;
-        Some(Packet::new(&part))
     }
 }
 
-fn order(l_str: &str, r_str:&str) -> Option<Order> {
-    let mut left = Packet::new(l_str);
-    let mut right: Packet = Packet::new(r_str);
+impl FromStr for Packet {
+    type Err = ParseError;
 
-    loop {
-        let l = left.next();
-        let r = right.next();
+    fn from_str(source: &str) -> Result<Packet, ParseError> {
+        let (packet, end) = Packet::parse_at(source.as_bytes(), 0)?;
+        match end == source.len() {
+            true => Ok(packet),
+            false => Err(ParseError { pos: end, message: String::from("unexpected data after packet") }),
+        }
+    }
+}
 
-        match (l, r) {
-            (None, None) => return None,
-            (None, Some(_)) => return Some(Order::Right),
-            (Some(_), None) => return Some(Order::Wrong),
-            (Some(lhs), Some(rhs)) => {
-                let (left_val, right_val):(Option<usize>, Option<usize>) = (lhs.get_digit(), rhs.get_digit());
-                if left_val.is_some() && right_val.is_some() {
-                    let lv = left_val.unwrap();
-                    let rv = right_val.unwrap();
-                    if lv == rv {
-                        continue
-                    }
-                    if lv < rv {
-                        return Some(Order::Right)
-                    } else {
-                        return Some(Order::Wrong)
+impl fmt::Display for Packet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Packet::Int(value) => write!(f, "{}", value),
+            Packet::List(items) => {
+                write!(f, "[")?;
+                for (idx, item) in items.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, ",")?;
                     }
+                    write!(f, "{}", item)?;
                 }
+                write!(f, "]")
+            }
+        }
+    }
+}
 
-                if left_val.is_none() && right_val.is_none() {
-                    let sub = order(&lhs.source, &rhs.source);
-                    if sub.is_none() {
-                        continue;
-                    } else {
-                        return sub
-                    }
-                }
+impl Packet {
+    // The puzzle's ordering, where packets in the right order compare as less. An integer
+    // compared with a list is treated as a list holding just that integer, so `4`, `[4]` and
+    // `[[4]]` are all in the same place
+    fn order(&self, other: &Packet) -> Ordering {
+        match (self, other) {
+            (Packet::Int(l), Packet::Int(r)) => l.cmp(r),
+            (Packet::List(l), Packet::List(r)) => Packet::order_lists(l, r),
+            (Packet::Int(_), Packet::List(r)) => Packet::order_lists(std::slice::from_ref(self), r),
+            (Packet::List(l), Packet::Int(_)) => Packet::order_lists(l, std::slice::from_ref(other)),
+        }
+    }
 
-                if left_val.is_some() {
-                    let sub = order(&Packet::from_digit(left_val.unwrap()), &rhs.source);
-                    if sub.is_none() {
-                        continue;
-                    } else {
-                        return sub
-                    }
-                }
+    fn order_lists(left: &[Packet], right: &[Packet]) -> Ordering {
+        left.iter()
+            .zip(right)
+            .map(|(l, r)| l.order(r))
+            .find(|order| *order != Ordering::Equal)
+            .unwrap_or_else(|| left.len().cmp(&right.len()))
+    }
+
+    // Orders packets by their structure alone, with integers before lists
+    fn cmp_structure(&self, other: &Packet) -> Ordering {
+        match (self, other) {
+            (Packet::Int(l), Packet::Int(r)) => l.cmp(r),
+            (Packet::Int(_), Packet::List(_)) => Ordering::Less,
+            (Packet::List(_), Packet::Int(_)) => Ordering::Greater,
+            (Packet::List(l), Packet::List(r)) => l.iter()
+                .zip(r)
+                .map(|(l, r)| l.cmp_structure(r))
+                .find(|order| *order != Ordering::Equal)
+                .unwrap_or_else(|| l.len().cmp(&r.len())),
+        }
+    }
+}
+
+// The puzzle's ordering, with packets it can't tell apart ordered by structure so that this
+// agrees with the derived equality
+impl Ord for Packet {
+    fn cmp(&self, other: &Packet) -> Ordering {
+        self.order(other).then_with(|| self.cmp_structure(other))
+    }
+}
+
+impl PartialOrd for Packet {
+    fn partial_cmp(&self, other: &Packet) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Side {
+    Left,
+    Right,
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Side::Left => write!(f, "left"),
+            Side::Right => write!(f, "right"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Event {
+    Compare(Packet, Packet),
+    // An integer on this side was wrapped in a list to compare it with a list
+    Promote(Side, u64),
+    // The comparison was decided by an integer on this side being smaller
+    Smaller(Side),
+    // The comparison was decided by the list on this side running out first
+    RanOut(Side),
+}
+
+// An index into the lists being compared. When one side was an integer wrapped in a list just
+// for the comparison the index is marked as promoted, as it only exists in the other packet
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct PathIndex {
+    idx: usize,
+    promoted: bool,
+}
+
+impl fmt::Display for PathIndex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{}]{}", self.idx, if self.promoted { "*" } else { "" })
+    }
+}
 
-                if right_val.is_some() {
-                    let sub = order(&lhs.source,  &Packet::from_digit(right_val.unwrap()),);
-                    if sub.is_none() {
-                        continue;
-                    } else {
-                        return sub
+// Path is the indexes into the top level lists of the values being compared, and depth how far
+// the step is nested in the walkthrough
+#[derive(Debug, PartialEq)]
+struct Step {
+    depth: usize,
+    path: Vec<PathIndex>,
+    event: Event,
+}
+
+impl Step {
+    // Where the compared values are, like `$[1][0]`
+    fn location(&self) -> String {
+        format!("${}", self.path.iter().map(|idx| idx.to_string()).collect::<String>())
+    }
+}
+
+// Every step of comparing two packets, mirroring `Packet::order`
+struct Explanation {
+    steps: Vec<Step>,
+    order: Ordering,
+}
+
+impl Explanation {
+    fn new(left: &Packet, right: &Packet) -> Explanation {
+        let mut steps = Vec::new();
+        let order = Explanation::compare(left, right, false, &mut Vec::new(), 0, &mut steps);
+        Explanation { steps, order }
+    }
+
+    fn compare(left: &Packet, right: &Packet, promoted: bool, path: &mut Vec<PathIndex>, depth: usize, steps: &mut Vec<Step>) -> Ordering {
+        let mut step = |depth, event| steps.push(Step { depth, path: path.clone(), event });
+        step(depth, Event::Compare(left.clone(), right.clone()));
+        match (left, right) {
+            (Packet::Int(l), Packet::Int(r)) => {
+                match l.cmp(r) {
+                    Ordering::Less => step(depth + 1, Event::Smaller(Side::Left)),
+                    Ordering::Greater => step(depth + 1, Event::Smaller(Side::Right)),
+                    Ordering::Equal => (),
+                }
+                l.cmp(r)
+            }
+            (Packet::Int(l), Packet::List(_)) => {
+                step(depth + 1, Event::Promote(Side::Left, *l));
+                Explanation::compare(&Packet::List(vec![left.clone()]), right, true, path, depth + 1, steps)
+            }
+            (Packet::List(_), Packet::Int(r)) => {
+                step(depth + 1, Event::Promote(Side::Right, *r));
+                Explanation::compare(left, &Packet::List(vec![right.clone()]), true, path, depth + 1, steps)
+            }
+            (Packet::List(l), Packet::List(r)) => {
+                for idx in 0.. {
+                    let order = match (l.get(idx), r.get(idx)) {
+                        (Some(l), Some(r)) => {
+                            path.push(PathIndex { idx, promoted });
+                            let order = Explanation::compare(l, r, false, path, depth + 1, steps);
+                            path.pop();
+                            order
+                        }
+                        (None, Some(_)) => {
+                            steps.push(Step { depth: depth + 1, path: path.clone(), event: Event::RanOut(Side::Left) });
+                            Ordering::Less
+                        }
+                        (Some(_), None) => {
+                            steps.push(Step { depth: depth + 1, path: path.clone(), event: Event::RanOut(Side::Right) });
+                            Ordering::Greater
+                        }
+                        (None, None) => return Ordering::Equal,
+                    };
+                    if order != Ordering::Equal {
+                        return order;
                     }
                 }
+                unreachable!()
             }
         }
     }
+
+    // The walkthrough in the puzzle's words, optionally with where each comparison is in the
+    // packets, like `$[1][0]`, with `*` after indexes into promoted integers
+    fn render(&self, show_paths: bool) -> String {
+        let verdict = |side: &Side| match side {
+            Side::Left => "so inputs are in the right order",
+            Side::Right => "so inputs are not in the right order",
+        };
+        self.steps.iter()
+            .map(|step| {
+                let text = match &step.event {
+                    Event::Compare(l, r) if show_paths => format!("Compare {} vs {} at {}", l, r, step.location()),
+                    Event::Compare(l, r) => format!("Compare {} vs {}", l, r),
+                    Event::Promote(side, value) => format!("Mixed types; convert {} to [{}] and retry comparison", side, value),
+                    Event::Smaller(Side::Left) => format!("Left side is smaller, {}", verdict(&Side::Left)),
+                    Event::Smaller(Side::Right) => format!("Right side is smaller, {}", verdict(&Side::Right)),
+                    Event::RanOut(Side::Left) => format!("Left side ran out of items, {}", verdict(&Side::Left)),
+                    Event::RanOut(Side::Right) => format!("Right side ran out of items, {}", verdict(&Side::Right)),
+                };
+                format!("{}- {}\n", "  ".repeat(step.depth), text)
+            })
+            .collect()
+    }
 }
 
+impl fmt::Display for Explanation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.render(false))
+    }
+}
+
+// The walkthrough for the given pairs, numbered from 1 like the puzzle
+fn explain_pairs(pairs: &[(Packet, Packet)], numbers: &[usize], show_paths: bool) -> Result<String, String> {
+    if let Some(n) = numbers.iter().find(|n| **n == 0 || **n > pairs.len()) {
+        return Err(format!("there is no pair {}, pairs are numbered from 1 to {}", n, pairs.len()));
+    }
+    let text = numbers.iter()
+        .map(|n| {
+            let (left, right) = &pairs[n - 1];
+            let explanation = Explanation::new(left, right);
+            let mut text = format!("== Pair {} ==\n{}", n, explanation.render(show_paths));
+            if explanation.order == Ordering::Equal {
+                text += "- Packets are the same, so the order can't be decided\n";
+            }
+            text
+        })
+        .collect::<Vec<String>>()
+        .join("\n");
+    Ok(text)
+}
+
+// Pairs of packets on consecutive lines, with blank lines between the pairs
+fn parse_pairs(source: &str) -> Result<Vec<(Packet, Packet)>, String> {
+    let mut pairs = Vec::new();
+    let mut group: Vec<(usize, Packet)> = Vec::new();
+    for (idx, line) in source.lines().chain(std::iter::once("")).enumerate() {
+        if !line.is_empty() {
+            let packet = line.parse().map_err(|e| format!("line {} {}", idx + 1, e))?;
+            group.push((idx + 1, packet));
+            continue;
+        }
+        match group.len() {
+            0 => (),
+            2 => {
+                let (_, right) = group.pop().unwrap();
+                let (_, left) = group.pop().unwrap();
+                pairs.push((left, right));
+            }
+            len => return Err(format!("line {}: expected a pair of packets, found {}", group[0].0, len)),
+        }
+    }
+    Ok(pairs)
+}
 
+fn all_packets(pairs: &[(Packet, Packet)]) -> Vec<Packet> {
+    pairs.iter().flat_map(|(left, right)| [left.clone(), right.clone()]).collect()
+}
+
+// Sum of the (1 based) indexes of the pairs that are in the right order
+fn ordered_pairs(pairs: &[(Packet, Packet)]) -> usize {
+    pairs.iter()
+        .enumerate()
+        .filter(|(_, (left, right))| left.order(right) == Ordering::Less)
+        .map(|(idx, _)| idx + 1)
+        .sum()
+}
+
+// Product of the (1 based) positions of the dividers once all the packets are sorted
+fn decoder_key(packets: &[Packet]) -> usize {
+    let dividers = [Packet::divider(2), Packet::divider(6)];
+    let mut signals = packets.to_vec();
+    signals.extend(dividers.iter().cloned());
+    signals.sort();
+
+    dividers.iter()
+        .map(|divider| signals.partition_point(|packet| packet.order(divider) == Ordering::Less) + 1)
+        .product()
+}
+
+// Reads a JSON array of packets, as written by `json`
+fn read_json_packets(path: &str) -> Result<Vec<Packet>, String> {
+    let source = fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let value: Value = serde_json::from_str(&source).map_err(|e| e.to_string())?;
+    match Packet::from_json(&value).map_err(|e| e.to_string())? {
+        Packet::List(packets) => Ok(packets),
+        Packet::Int(_) => Err(String::from("$: expected an array of packets")),
+    }
+}
+
+const USAGE: &str = "Usage: day13 [json | from-json <file> | explain [pair] | paths [pair]]";
+
+// With no arguments this solves the puzzle, otherwise:
+//   json                prints the input packets as a JSON array
+//   from-json <file>    reads a JSON array of packets and prints them in the puzzle's format
+//   explain [pair]      walks through comparing every pair, or just the numbered one
+//   paths [pair]        the same, with where each comparison is in the packets
 fn main() {
     let input_data = fs::read_to_string("src/day13/input.txt").unwrap();
+    let pairs = match parse_pairs(&input_data) {
+        Ok(pairs) => pairs,
+        Err(e) => {
+            eprintln!("Invalid input - {}", e);
+            return;
+        }
+    };
+    let packets = all_packets(&pairs);
 
-    let result1 = input_data.split("\n\n")
-        .map(|pair_str| pair_str.split_once("\n"))
-        .map(|result| result.unwrap())
-        .enumerate()
-        .map(|(e, (left_str, right_str))| ( e+1, (left_str, right_str)))
-        .map(|(idx, (left, right))| (idx, order(left,right).unwrap()))
-        .filter(|(_, res)| *res == Order::Right)
-        .map(|(id, _)| id)
-        .sum::<usize>();
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    match args.iter().map(String::as_str).collect::<Vec<&str>>().as_slice() {
+        ["json"] => println!("{}", Packet::List(packets).to_json()),
+        ["from-json", path] => match read_json_packets(path) {
+            Ok(packets) => packets.iter().for_each(|packet| println!("{}", packet)),
+            Err(e) => eprintln!("Invalid packets in {} - {}", path, e),
+        },
+        [command @ ("explain" | "paths"), pair @ ..] if pair.len() <= 1 => {
+            let numbers = match pair {
+                [n] => match n.parse::<usize>() {
+                    Ok(n) => vec![n],
+                    Err(_) => return eprintln!("{}", USAGE),
+                },
+                _ => (1..=pairs.len()).collect(),
+            };
+            match explain_pairs(&pairs, &numbers, *command == "paths") {
+                Ok(text) => print!("{}", text),
+                Err(e) => eprintln!("Can't explain - {}", e),
+            }
+        }
+        [] => {
+            println!("Part 1 - Result: {}", ordered_pairs(&pairs));
+            println!("Part 2 - Result: {}", decoder_key(&packets));
+        }
+        _ => eprintln!("{}", USAGE),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    println!("Part 1 - Result: {}", result1);
+    #[test]
+    fn day13_example() {
+        let input_data = fs::read_to_string("src/day13/example.txt").unwrap();
+        let pairs = parse_pairs(&input_data).unwrap();
+        let packets = all_packets(&pairs);
+        assert_eq!(packets.len(), 16);
+        assert_eq!(ordered_pairs(&pairs), 13);
+        assert_eq!(decoder_key(&packets), 140);
 
-    let mut signals = input_data.lines()
-        .filter(|line| !line.is_empty())
-        .chain(vec!["[[2]]", "[[6]]"])
-        .collect::<Vec<&str>>();
+        // Printing gives back the input
+        let printed = packets.iter().map(|packet| packet.to_string()).collect::<Vec<String>>();
+        assert_eq!(printed, input_data.lines().filter(|line| !line.is_empty()).collect::<Vec<&str>>());
+    }
+
+    #[test]
+    fn day13_packets() {
+        let packet = |source: &str| source.parse::<Packet>().unwrap();
+        assert_eq!(packet("[1,[2,[]],10]"), Packet::List(vec![
+            Packet::Int(1),
+            Packet::List(vec![Packet::Int(2), Packet::List(vec![])]),
+            Packet::Int(10),
+        ]));
+        assert!(packet("[[1],[2,3,4]]") < packet("[[1],4]"));
+        assert!(packet("[9]") > packet("[[8,7,6]]"));
+        assert!(packet("[[[]]]") > packet("[[]]"));
+        assert_eq!(packet("[[4]]").order(&packet("4")), Ordering::Equal);
+        assert_ne!(packet("[[4]]"), packet("4"));
+        assert_eq!(packet("[[4]]").cmp(&packet("4")), Ordering::Greater);
+        assert_eq!(packet("[[4]]").cmp(&packet("[[4]]")), Ordering::Equal);
+        assert_eq!(packet("[[4]]").to_string(), "[[4]]");
+
+        let error = |source: &str| source.parse::<Packet>().err().unwrap();
+        assert_eq!(error("[1,2"), ParseError { pos: 4, message: "unclosed list".to_string() });
+        assert_eq!(error("[1;2]").pos, 2);
+        assert_eq!(error("[1,]").message, "expected '[' or an integer");
+        assert_eq!(error("[1]]").message, "unexpected data after packet");
+        assert_eq!(error("[99999999999999999999]").message, "integer too large");
+
+        assert_eq!(parse_pairs("[1]\n[2]\n\n[3]\n").err().unwrap(), "line 4: expected a pair of packets, found 1");
+        assert_eq!(parse_pairs("[1]\n[2]\n[3]\n\n[4]\n[5]").err().unwrap(), "line 1: expected a pair of packets, found 3");
+        assert_eq!(parse_pairs("[1]\n[2\n").err().unwrap(), "line 2 at 2: unclosed list");
+    }
 
-    signals.sort_by(|a, b| {
-        let o = order(*a,*b).unwrap();
-        match o {
-            Order::Right => Ordering::Less,
-            Order::Wrong => Ordering::Greater,
+    #[test]
+    fn day13_json() {
+        let input_data = fs::read_to_string("src/day13/example.txt").unwrap();
+        for packet in all_packets(&parse_pairs(&input_data).unwrap()) {
+            let value: Value = serde_json::from_str(&packet.to_string()).unwrap();
+            assert_eq!(packet.to_json(), value);
+            assert_eq!(Packet::from_json(&value), Ok(packet));
         }
-    });
 
-    let result2 = signals.iter()
-        .enumerate()
-        .map(|(i, line)| (i+1, line))
-        .filter(|(_, line) | {
-            *line == &"[[2]]" || *line == &"[[6]]"
-        })
-        .map(|(i, _)| i)
-        .product::<usize>();
+        let error = |source: &str| Packet::from_json(&serde_json::from_str(source).unwrap()).err().unwrap();
+        assert_eq!(error("[1,[2,{}]]"), JsonError { path: String::from("$[1][1]"), kind: JsonErrorKind::Object });
+        assert_eq!(error("[[],\"3\"]").kind, JsonErrorKind::String);
+        assert_eq!(error("[[-1]]").to_string(), "$[0][0]: integers in packets can't be negative");
+        assert_eq!(error("[1.5]").kind, JsonErrorKind::Float);
+        assert_eq!(error("[[1e3]]").path, "$[0][0]");
+        assert_eq!(error("[18446744073709551616]").to_string(), "$[0]: integer too large");
+        assert_eq!(error("[-0.5]").kind, JsonErrorKind::Float);
+        assert_eq!(error("[true]").kind, JsonErrorKind::Bool);
+        assert_eq!(error("null").path, "$");
+        assert!(read_json_packets("src/day13/missing.json").is_err());
+    }
 
-    println!("Part 2 - Result: {}", result2);
+    #[test]
+    fn day13_explanations() {
+        let input_data = fs::read_to_string("src/day13/example.txt").unwrap();
+        let pairs = parse_pairs(&input_data).unwrap();
+        for (left, right) in pairs.iter() {
+            assert_eq!(Explanation::new(left, right).order, left.order(right));
+        }
+
+        assert_eq!(explain_pairs(&pairs, &[2, 3], false).unwrap(), "\
+== Pair 2 ==
+- Compare [[1],[2,3,4]] vs [[1],4]
+  - Compare [1] vs [1]
+    - Compare 1 vs 1
+  - Compare [2,3,4] vs 4
+    - Mixed types; convert right to [4] and retry comparison
+    - Compare [2,3,4] vs [4]
+      - Compare 2 vs 4
+        - Left side is smaller, so inputs are in the right order
+
+== Pair 3 ==
+- Compare [9] vs [[8,7,6]]
+  - Compare 9 vs [8,7,6]
+    - Mixed types; convert left to [9] and retry comparison
+    - Compare [9] vs [8,7,6]
+      - Compare 9 vs 8
+        - Right side is smaller, so inputs are not in the right order
+");
+
+        let explanation = Explanation::new(&pairs[4].0, &pairs[4].1);
+        assert_eq!(explanation.to_string().lines().last(), Some("  - Right side ran out of items, so inputs are not in the right order"));
+        let explanation = Explanation::new(&pairs[7].0, &pairs[7].1);
+        assert_eq!(explanation.order, Ordering::Greater);
+        let last = explanation.steps.last().unwrap();
+        assert_eq!((last.depth, &last.event), (6, &Event::Smaller(Side::Right)));
+        assert_eq!(last.location(), "$[1][1][1][1][2]");
+        assert_eq!(explanation.render(true).lines().rev().nth(1), Some("          - Compare 7 vs 0 at $[1][1][1][1][2]"));
 
-}
\ No newline at end of file
+        // 9 is wrapped in a list to compare it with [8,7,6], so its index only exists on the right
+        let explanation = Explanation::new(&pairs[2].0, &pairs[2].1);
+        assert_eq!(explanation.render(true).lines().rev().nth(1), Some("      - Compare 9 vs 8 at $[0][0]*"));
+
+        assert_eq!(explain_pairs(&pairs, &[9], false), Err("there is no pair 9, pairs are numbered from 1 to 8".to_string()));
+        assert!(explain_pairs(&pairs, &[0], false).is_err());
+
+        let same = parse_pairs("[1,[2]]\n[1,[2]]").unwrap();
+        assert_eq!(explain_pairs(&same, &[1], false).unwrap().lines().last(), Some("- Packets are the same, so the order can't be decided"));
+    }
+}